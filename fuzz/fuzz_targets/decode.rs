@@ -0,0 +1,18 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use squad_rcon::RconPacket;
+
+const MAX_BODY_LENGTH: usize = 4096;
+
+fuzz_target!(|data: &[u8]| {
+    let mut reader = data;
+    while let Ok(packet) = RconPacket::decode(&mut reader, MAX_BODY_LENGTH) {
+        // Anything we can decode must survive being encoded and decoded again.
+        // Lossy decoding can grow the body, so allow for that on the way back.
+        let encoded = packet.encode().unwrap();
+        let decoded = RconPacket::decode(&mut &encoded[..], MAX_BODY_LENGTH * 3).unwrap();
+        assert_eq!(decoded.id(), packet.id());
+        assert_eq!(decoded.type_(), packet.type_());
+        assert_eq!(decoded.body(), packet.body());
+    }
+});
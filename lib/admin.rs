@@ -0,0 +1,224 @@
+use crate::event::{BANNED_REGEX, KICKED_REGEX, WARNED_REGEX};
+use crate::Error;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+lazy_static! {
+    static ref FORCED_TEAM_CHANGE_REGEX: Regex =
+        Regex::new(r"^Forced team change for player (\d*)\. \[steamid=(\d*)\] (.*)$")
+            .expect("FORCED_TEAM_CHANGE_REGEX");
+    // Failures are only recognised at the start of a response, so that
+    // messages and names echoed back by the server are never mistaken for one
+    static ref INVALID_LAYER_REGEX: Regex = Regex::new(
+        r"^(?:Error: )?(?:\S+ is not a valid (?:map|layer)|(?:Invalid|Unknown) (?:map|layer)\b|Could(?: not|n't) find (?:map|layer)\b)"
+    )
+    .expect("INVALID_LAYER_REGEX");
+    static ref AMBIGUOUS_NAME_REGEX: Regex =
+        Regex::new(r"^(?:Error: )?(?:Multiple players match|Found multiple players)")
+            .expect("AMBIGUOUS_NAME_REGEX");
+    static ref PLAYER_NOT_FOUND_REGEX: Regex =
+        Regex::new(r"^(?:Error: )?Could(?: not|n't) find player\b")
+            .expect("PLAYER_NOT_FOUND_REGEX");
+    static ref FAILED_REGEX: Regex =
+        Regex::new(r"^(?:Error:|Failed to |Unable to |Could(?: not|n't) )")
+            .expect("FAILED_REGEX");
+}
+
+/// What an admin command acts on, which decides how a failure is recognised
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Target {
+    /// The match or the server settings
+    Server,
+    /// A message to players or admins. The response may echo the message, so
+    /// it is always read as a plain success.
+    Message,
+    /// A player, found by name or id
+    Player,
+    /// A map or layer
+    Layer,
+}
+
+/// The outcome of an admin action, such as `SquadRcon::kick`, which the
+/// server accepted.
+///
+/// Responses which mean the action failed are returned as errors instead,
+/// such as `Error::PlayerNotFound`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum AdminActionResult {
+    /// The action was applied to a player. The id and steam id are only
+    /// reported for some actions.
+    Player {
+        id: Option<usize>,
+        steam_id: Option<String>,
+        name: String,
+    },
+    /// The action succeeded, with the server's response as sent
+    Success(String),
+}
+
+impl AdminActionResult {
+    /// Parse the server's response to an admin command which acts on the
+    /// server, such as `AdminEndMatch`.
+    ///
+    /// Responses which start with a failure, such as `Failed to`, are
+    /// returned as `Error::CommandFailed`.
+    pub fn parse(response: &str) -> Result<AdminActionResult, Error> {
+        AdminActionResult::parse_for(Target::Server, response)
+    }
+
+    /// Parse the server's response to an admin command acting on `target`.
+    ///
+    /// Player lookup failures are only recognised for commands which take a
+    /// player, and unknown layers for commands which take a layer.
+    pub(crate) fn parse_for(target: Target, response: &str) -> Result<AdminActionResult, Error> {
+        let response = response.trim();
+        let player = |id: &str, steam_id: &str, name: &str| AdminActionResult::Player {
+            id: id.parse().ok(),
+            steam_id: Some(steam_id.to_string()),
+            name: name.to_string(),
+        };
+
+        if target == Target::Message {
+            Ok(AdminActionResult::Success(response.to_string()))
+        } else if let Some(captures) = KICKED_REGEX.captures(response) {
+            Ok(player(&captures[1], &captures[2], &captures[3]))
+        } else if let Some(captures) = BANNED_REGEX.captures(response) {
+            Ok(player(&captures[1], &captures[2], &captures[3]))
+        } else if let Some(captures) = FORCED_TEAM_CHANGE_REGEX.captures(response) {
+            Ok(player(&captures[1], &captures[2], &captures[3]))
+        } else if let Some(captures) = WARNED_REGEX.captures(response) {
+            Ok(AdminActionResult::Player {
+                id: None,
+                steam_id: None,
+                name: captures[1].to_string(),
+            })
+        } else if target == Target::Layer && INVALID_LAYER_REGEX.is_match(response) {
+            Err(Error::InvalidLayer(response.to_string()))
+        } else if target == Target::Player && AMBIGUOUS_NAME_REGEX.is_match(response) {
+            Err(Error::AmbiguousName(response.to_string()))
+        } else if target == Target::Player && PLAYER_NOT_FOUND_REGEX.is_match(response) {
+            Err(Error::PlayerNotFound(response.to_string()))
+        } else if FAILED_REGEX.is_match(response) {
+            Err(Error::CommandFailed(response.to_string()))
+        } else {
+            Ok(AdminActionResult::Success(response.to_string()))
+        }
+    }
+
+    /// The name of the player the action was applied to, if it concerned
+    /// one player
+    pub fn player_name(&self) -> Option<&str> {
+        match self {
+            AdminActionResult::Player { name, .. } => Some(name),
+            AdminActionResult::Success(_) => None,
+        }
+    }
+}
+
+impl std::fmt::Display for AdminActionResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AdminActionResult::Player {
+                steam_id: Some(steam_id),
+                name,
+                ..
+            } => write!(f, "{} ({})", name, steam_id),
+            AdminActionResult::Player { name, .. } => write!(f, "{}", name),
+            AdminActionResult::Success(response) => write!(f, "{}", response),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AdminActionResult, Target};
+    use crate::mock::fixtures::server;
+    use crate::{Error, SquadRcon};
+
+    #[test]
+    fn player() {
+        match AdminActionResult::parse("Kicked player 3. [steamid=76561198000000002] Bob\n") {
+            Ok(AdminActionResult::Player { id, steam_id, name }) => {
+                assert_eq!(id, Some(3));
+                assert_eq!(steam_id.unwrap(), "76561198000000002");
+                assert_eq!(name, "Bob");
+            }
+            other => panic!("{:?}", other),
+        }
+        let result =
+            AdminActionResult::parse("Remote admin has warned player Bob. Message was \"hi\"")
+                .unwrap();
+        assert_eq!(result.player_name(), Some("Bob"));
+    }
+
+    #[test]
+    fn failures() {
+        match AdminActionResult::parse_for(Target::Player, "Could not find player Bob") {
+            Err(Error::PlayerNotFound(_)) => {}
+            other => panic!("{:?}", other),
+        }
+        match AdminActionResult::parse_for(Target::Player, "Multiple players matching Bo") {
+            Err(Error::AmbiguousName(_)) => {}
+            other => panic!("{:?}", other),
+        }
+        match AdminActionResult::parse_for(Target::Layer, "Narva_XYZ is not a valid layer") {
+            Err(Error::InvalidLayer(_)) => {}
+            other => panic!("{:?}", other),
+        }
+        match AdminActionResult::parse("Failed to disband squad") {
+            Err(Error::CommandFailed(_)) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn success() {
+        match AdminActionResult::parse("Message broadcasted") {
+            Ok(AdminActionResult::Success(response)) => assert_eq!(response, "Message broadcasted"),
+            other => panic!("{:?}", other),
+        }
+        match AdminActionResult::parse_for(
+            Target::Message,
+            "Kicked player 3. [steamid=76561198000000002] Bob",
+        ) {
+            Ok(AdminActionResult::Success(_)) => {}
+            other => panic!("{:?}", other),
+        }
+        // Failures are only recognised where they can happen, and at the
+        // start of the response
+        for (target, response) in [
+            (
+                Target::Message,
+                "Failed to find the ammo crate, could not find player Bob",
+            ),
+            (
+                Target::Server,
+                "Message broadcasted: could not find player Bob",
+            ),
+            (Target::Player, "Message was \"Invalid layer\""),
+        ] {
+            assert!(
+                AdminActionResult::parse_for(target, response).is_ok(),
+                "{:?}",
+                response
+            );
+        }
+    }
+
+    #[test]
+    fn admin_action_failure() {
+        let server = server();
+        server.set_response("AdminKick \"Carol\" afk", "Could not find player Carol");
+        let mut squad_rcon = SquadRcon::connect(server.addr(), "password").unwrap();
+
+        match squad_rcon.kick("Carol", "afk") {
+            Err(Error::PlayerNotFound(response)) => {
+                assert_eq!(response, "Could not find player Carol")
+            }
+            other => panic!("{:?}", other),
+        }
+        // The connection is still usable
+        assert_eq!(squad_rcon.players().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,381 @@
+use crate::rcon::{
+    RconPacket, HEADER_SIZE, SERVERDATA_AUTH, SERVERDATA_AUTH_RESPONSE, SERVERDATA_EXECCOMMAND,
+    SERVERDATA_RESPONSE_VALUE,
+};
+use crate::terminator::DETECTION_TIMEOUT;
+use crate::{Error, RconConfig, Terminator};
+use log::debug;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{lookup_host, TcpStream, ToSocketAddrs};
+
+/// An asynchronous rcon client, built on tokio.
+///
+/// This speaks the same protocol as `RconClient`, and shares its packet
+/// encoding and decoding.
+///
+/// The connect, read and write timeouts, the maximum body length and the
+/// terminator of its `RconConfig` are honoured. There is no heartbeat, and
+/// the command timeout is not applied; wrap calls in `tokio::time::timeout`
+/// instead.
+pub struct AsyncRconClient {
+    next_id: i32,
+    password: String,
+    stream: TcpStream,
+    /// Resolved again on reconnect, if the client was made with
+    /// `AsyncRconClient::connect_host`
+    host: Option<String>,
+    addrs: Vec<SocketAddr>,
+    config: RconConfig,
+    terminator: Terminator,
+    /// Packets which arrived while detecting the terminator, returned with
+    /// the next response
+    early_packets: Vec<RconPacket>,
+}
+
+impl AsyncRconClient {
+    /// Connect to, and authenticate with, an rcon server.
+    ///
+    /// Every address `addr` resolves to is tried in turn, as with
+    /// `TcpTransport::connect`.
+    pub async fn connect<A: ToSocketAddrs, S: Into<String>>(
+        addr: A,
+        password: S,
+    ) -> Result<AsyncRconClient, Error> {
+        AsyncRconClient::connect_with_config(addr, password, RconConfig::default()).await
+    }
+
+    /// Connect to, and authenticate with, an rcon server, with the given
+    /// timeouts and settings
+    pub async fn connect_with_config<A: ToSocketAddrs, S: Into<String>>(
+        addr: A,
+        password: S,
+        config: RconConfig,
+    ) -> Result<AsyncRconClient, Error> {
+        let addrs = lookup_host(addr).await?.collect::<Vec<SocketAddr>>();
+        AsyncRconClient::connect_addrs(None, addrs, password.into(), config).await
+    }
+
+    /// Connect to, and authenticate with, an rcon server at `host`, such as
+    /// `"example.com:21114"`. The host is resolved again each time the
+    /// client reconnects.
+    pub async fn connect_host<H: Into<String>, S: Into<String>>(
+        host: H,
+        password: S,
+        config: RconConfig,
+    ) -> Result<AsyncRconClient, Error> {
+        let host = host.into();
+        let addrs = lookup_host(host.as_str())
+            .await?
+            .collect::<Vec<SocketAddr>>();
+        AsyncRconClient::connect_addrs(Some(host), addrs, password.into(), config).await
+    }
+
+    async fn connect_addrs(
+        host: Option<String>,
+        addrs: Vec<SocketAddr>,
+        password: String,
+        config: RconConfig,
+    ) -> Result<AsyncRconClient, Error> {
+        let mut rcon_client = AsyncRconClient {
+            next_id: 10,
+            password,
+            stream: connect_any(&addrs, config.connect_timeout()).await?,
+            host,
+            addrs,
+            terminator: config.terminator(),
+            config,
+            early_packets: Vec::new(),
+        };
+        rcon_client.authenticate().await?;
+        rcon_client.terminator = rcon_client.detect_terminator().await?;
+        Ok(rcon_client)
+    }
+
+    /// Reconnect and re-authenticate. The host is resolved again if the
+    /// client was made with `AsyncRconClient::connect_host`, otherwise each
+    /// address it resolved to is tried again.
+    pub async fn reconnect(&mut self) -> Result<(), Error> {
+        if let Some(host) = &self.host {
+            self.addrs = lookup_host(host.as_str()).await?.collect();
+        }
+        self.stream = connect_any(&self.addrs, self.config.connect_timeout()).await?;
+        self.authenticate().await?;
+        self.terminator = self.detect_terminator().await?;
+        Ok(())
+    }
+
+    /// The settings this client was connected with
+    pub fn config(&self) -> &RconConfig {
+        &self.config
+    }
+
+    /// How the end of each response is recognised. Once connected, this is
+    /// never `Terminator::Auto`.
+    pub fn terminator(&self) -> Terminator {
+        self.terminator
+    }
+
+    /// Resolve `Terminator::Auto`, as `RconClient` does
+    async fn detect_terminator(&mut self) -> Result<Terminator, Error> {
+        if self.config.terminator() != Terminator::Auto {
+            return Ok(self.config.terminator());
+        }
+
+        let probe_id = self.get_next_id();
+        self.send_packet(&Terminator::EmptyResponse.probe(probe_id).unwrap())
+            .await?;
+
+        let detection_timeout = match self.config.read_timeout() {
+            Some(read_timeout) => read_timeout.min(DETECTION_TIMEOUT),
+            None => DETECTION_TIMEOUT,
+        };
+        let terminator = loop {
+            match self.recv_packet_within(Some(detection_timeout)).await? {
+                Some(packet) if packet.id() == probe_id => break Terminator::EmptyResponse,
+                Some(packet) => self.early_packets.push(packet),
+                None => break Terminator::ShowNextMap,
+            }
+        };
+
+        debug!("Using {:?} to terminate responses", terminator);
+        Ok(terminator)
+    }
+
+    /// Authenticate with the rcon server.
+    ///
+    /// This is automatically called by `AsyncRconClient::connect`
+    pub async fn authenticate(&mut self) -> Result<(), Error> {
+        let authentication_packet =
+            RconPacket::new(self.get_next_id(), SERVERDATA_AUTH, self.password());
+
+        let response_packet = self.send_and_get_response(&authentication_packet).await?;
+        if response_packet.type_() != SERVERDATA_RESPONSE_VALUE {
+            return Err(Error::ProtocolError);
+        }
+
+        let response_packet = self.recv_packet().await?;
+        if response_packet.type_() != SERVERDATA_AUTH_RESPONSE {
+            return Err(Error::ProtocolError);
+        }
+
+        if response_packet.id() == -1 {
+            return Err(Error::AuthenticationFailure);
+        }
+
+        Ok(())
+    }
+
+    /// Send an rcon packet to the server
+    pub async fn send_packet(&mut self, packet: &RconPacket) -> Result<(), Error> {
+        let data = packet.encode()?;
+        let write_timeout = self.config.write_timeout();
+        within(write_timeout, self.stream.write_all(&data)).await??;
+        Ok(())
+    }
+
+    async fn recv_buf(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        let mut total_bytes_read = 0;
+        while total_bytes_read < buf.len() {
+            let bytes_read = self.stream.read(&mut buf[total_bytes_read..]).await?;
+            if bytes_read == 0 {
+                return Err(Error::Disconnected);
+            }
+            total_bytes_read += bytes_read;
+        }
+        Ok(())
+    }
+
+    /// Receive an rcon packet from the server
+    pub async fn recv_packet(&mut self) -> Result<RconPacket, Error> {
+        self.recv_packet_within(self.config.read_timeout())
+            .await?
+            .ok_or(Error::Timeout)
+    }
+
+    /// Receive a packet, waiting at most `wait` for it to begin, or forever
+    /// if `wait` is `None`. Returns `None` if nothing arrived in time.
+    ///
+    /// Once a packet has begun, the rest of it is read with the usual read
+    /// timeout, so a short `wait` never leaves the connection part way
+    /// through a packet.
+    async fn recv_packet_within(
+        &mut self,
+        wait: Option<Duration>,
+    ) -> Result<Option<RconPacket>, Error> {
+        let mut header = [0; HEADER_SIZE];
+        // Reading from a tokio stream is cancel safe, so nothing is lost if
+        // the wait ends first
+        match within(wait, self.stream.read(&mut header[..1])).await {
+            Ok(Ok(0)) => return Err(Error::Disconnected),
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => return Err(e.into()),
+            Err(Error::Timeout) => return Ok(None),
+            Err(e) => return Err(e),
+        }
+
+        let read_timeout = self.config.read_timeout();
+        let max_body_length = self.config.max_body_length();
+        within(read_timeout, async {
+            self.recv_buf(&mut header[1..]).await?;
+            let (remaining, id, type_) = RconPacket::decode_header(&header, max_body_length)?;
+
+            let mut body = vec![0; remaining];
+            self.recv_buf(&mut body).await?;
+
+            RconPacket::decode_body(id, type_, body).map(Some)
+        })
+        .await?
+    }
+
+    /// Send an rcon packet and wait for the response
+    pub async fn send_and_get_response(
+        &mut self,
+        packet: &RconPacket,
+    ) -> Result<RconPacket, Error> {
+        self.send_packet(packet).await?;
+        self.recv_packet().await
+    }
+
+    /// Get the next id for an rcon packet to send
+    pub fn get_next_id(&mut self) -> i32 {
+        let next_id = self.next_id;
+        self.next_id += 1;
+        if self.next_id > 100000 {
+            self.next_id = 10;
+        }
+        next_id
+    }
+
+    /// The password used to initiate this rcon connection
+    pub fn password(&self) -> &str {
+        &self.password
+    }
+
+    /// Execute an rcon command, and return the entire response from the server,
+    /// as well as any other packets which were on the line (these are most
+    /// likely chat messages).
+    ///
+    /// The end of the response is recognised per `AsyncRconClient::terminator`.
+    pub async fn exec_command2<S: Into<String>>(
+        &mut self,
+        command: S,
+    ) -> Result<(String, Vec<RconPacket>), Error> {
+        let mut body_parts: Vec<String> = Vec::new();
+
+        let request_id = self.get_next_id();
+        let request_packet = RconPacket::new(request_id, SERVERDATA_EXECCOMMAND, command.into());
+        self.send_packet(&request_packet).await?;
+
+        // Make sure we've received all the data in the current packet
+        let (chk_id, quiet) = match self.terminator {
+            Terminator::Quiet(quiet) => (None, Some(quiet)),
+            terminator => {
+                let chk_id = self.get_next_id();
+                self.send_packet(&terminator.probe(chk_id).unwrap()).await?;
+                (Some(chk_id), None)
+            }
+        };
+
+        let mut other_packets = Vec::new();
+
+        loop {
+            let wait = match quiet {
+                Some(quiet) if !body_parts.is_empty() => Some(quiet),
+                _ => self.config.read_timeout(),
+            };
+            let response = match self.recv_packet_within(wait).await? {
+                Some(response) => response,
+                None if quiet.is_some() && !body_parts.is_empty() => break,
+                None => return Err(Error::Timeout),
+            };
+            if response.id() == request_id {
+                body_parts.push(response.into_body());
+            } else if Some(response.id()) == chk_id {
+                break;
+            } else {
+                other_packets.push(response);
+            }
+        }
+
+        other_packets.splice(0..0, self.early_packets.drain(..));
+        Ok((body_parts.join(""), other_packets))
+    }
+}
+
+/// Run `future`, failing with `Error::Timeout` if it takes longer than
+/// `timeout`
+async fn within<F: Future>(timeout: Option<Duration>, future: F) -> Result<F::Output, Error> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, future)
+            .await
+            .map_err(|_| Error::Timeout),
+        None => Ok(future.await),
+    }
+}
+
+/// Connect to the first reachable address of `addrs`, waiting at most
+/// `connect_timeout` for each
+async fn connect_any(
+    addrs: &[SocketAddr],
+    connect_timeout: Option<Duration>,
+) -> Result<TcpStream, Error> {
+    let mut errors = Vec::new();
+    for addr in addrs {
+        let stream = within(connect_timeout, TcpStream::connect(addr)).await;
+        match stream.and_then(|stream| stream.map_err(Error::from)) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => errors.push((*addr, e)),
+        }
+    }
+    if errors.is_empty() {
+        Err(Error::NoAddresses(String::new()))
+    } else {
+        Err(Error::ConnectFailed(errors))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mock::fixtures::{exact_config, server};
+    use crate::{AsyncRconClient, Error, RconConfig, Terminator};
+
+    #[tokio::test]
+    async fn async_config() {
+        let server = server();
+        let config = RconConfig::new().with_terminator(Terminator::Auto);
+        let mut rcon_client =
+            AsyncRconClient::connect_with_config(server.addr(), "password", config)
+                .await
+                .unwrap();
+        assert_eq!(rcon_client.terminator(), Terminator::EmptyResponse);
+        rcon_client.exec_command2("ListPlayers").await.unwrap();
+        assert_eq!(server.received_commands(), vec!["ListPlayers"]);
+
+        let config = RconConfig::new().with_max_body_length(16);
+        let mut rcon_client =
+            AsyncRconClient::connect_with_config(server.addr(), "password", config)
+                .await
+                .unwrap();
+        match rcon_client.exec_command2("ListPlayers").await {
+            Err(Error::PacketTooLarge(_)) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn reconnect_to_host() {
+        let server = server();
+        let host = format!("localhost:{}", server.addr().port());
+        let mut rcon_client = AsyncRconClient::connect_host(host, "password", exact_config())
+            .await
+            .unwrap();
+
+        server.disconnect_all();
+        rcon_client.reconnect().await.unwrap();
+        rcon_client.exec_command2("ListPlayers").await.unwrap();
+        assert_eq!(server.received_commands(), vec!["ListPlayers"]);
+    }
+}
@@ -0,0 +1,608 @@
+use crate::admin::Target;
+use crate::async_rcon::AsyncRconClient;
+use crate::squad_rcon::{self, LayerFormat, SERVERDATA_CHAT};
+use crate::{commands, parse, AdminActionResult, ServerEvent};
+use crate::{
+    Chat, Error, Layer, LayerInfo, MatchRule, Player, PlayerList, Preset, RconConfig, Squad, Team,
+};
+use tokio::net::ToSocketAddrs;
+
+/// A squad-specific asynchronous rcon connection.
+///
+/// This offers the same commands as `SquadRcon`, returning futures.
+pub struct AsyncSquadRcon {
+    rcon_client: AsyncRconClient,
+    event_log: Vec<String>,
+    /// How the server reports layers, once it is known
+    layer_format: Option<LayerFormat>,
+}
+
+impl AsyncSquadRcon {
+    pub async fn connect<A: ToSocketAddrs, S: Into<String>>(
+        addr: A,
+        password: S,
+    ) -> Result<AsyncSquadRcon, Error> {
+        Ok(AsyncSquadRcon {
+            rcon_client: AsyncRconClient::connect(addr, password).await?,
+            event_log: Vec::new(),
+            layer_format: None,
+        })
+    }
+
+    /// Connect with the given timeouts and settings. See `AsyncRconClient`
+    /// for which settings apply.
+    pub async fn connect_with_config<A: ToSocketAddrs, S: Into<String>>(
+        addr: A,
+        password: S,
+        config: RconConfig,
+    ) -> Result<AsyncSquadRcon, Error> {
+        Ok(AsyncSquadRcon {
+            rcon_client: AsyncRconClient::connect_with_config(addr, password, config).await?,
+            event_log: Vec::new(),
+            layer_format: None,
+        })
+    }
+
+    /// Connect to `host`, such as `"example.com:21114"`, with the given
+    /// timeouts and settings. The host is resolved again each time the
+    /// connection is re-established.
+    pub async fn connect_host<H: Into<String>, S: Into<String>>(
+        host: H,
+        password: S,
+        config: RconConfig,
+    ) -> Result<AsyncSquadRcon, Error> {
+        Ok(AsyncSquadRcon {
+            rcon_client: AsyncRconClient::connect_host(host, password, config).await?,
+            event_log: Vec::new(),
+            layer_format: None,
+        })
+    }
+
+    pub async fn reconnect(&mut self) -> Result<(), Error> {
+        // The server may have been updated while it was away
+        self.layer_format = None;
+        self.rcon_client.reconnect().await
+    }
+
+    /// Take every event received from the server so far. This resets the
+    /// event log.
+    pub fn take_events(&mut self) -> Vec<ServerEvent> {
+        std::mem::take(&mut self.event_log)
+            .iter()
+            .map(|body| ServerEvent::parse(body))
+            .collect()
+    }
+
+    /// Take the chat messages received from the server so far. This resets
+    /// the event log, discarding any events which are not chat messages.
+    pub fn take_chat_log(&mut self) -> Result<Vec<Chat>, Error> {
+        Ok(self
+            .take_events()
+            .into_iter()
+            .filter_map(|event| match event {
+                ServerEvent::Chat(chat) => Some(chat),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// Execute a raw rcon command, and return the result.
+    ///
+    /// This is a convenience wrapper around `AsyncRconClient::exec_command2`.
+    pub async fn raw_command<S: Into<String>>(&mut self, command: S) -> Result<String, Error> {
+        let (response, other_packets) = self.rcon_client.exec_command2(command).await?;
+
+        for packet in other_packets {
+            if packet.type_() == SERVERDATA_CHAT {
+                self.event_log.push(packet.body().to_string());
+            }
+        }
+
+        Ok(response)
+    }
+
+    /// Execute an admin command, and parse the server's response
+    async fn admin_command<S: Into<String>>(
+        &mut self,
+        target: Target,
+        command: S,
+    ) -> Result<AdminActionResult, Error> {
+        AdminActionResult::parse_for(target, &self.raw_command(command).await?)
+    }
+
+    /// Return all of the players on the squad server
+    pub async fn players(&mut self) -> Result<Vec<Player>, Error> {
+        parse::players(&self.raw_command(commands::LIST_PLAYERS).await?)
+    }
+
+    /// Return the players on the squad server, and the players who left it
+    /// recently
+    pub async fn player_list(&mut self) -> Result<PlayerList, Error> {
+        parse::player_list(&self.raw_command(commands::LIST_PLAYERS).await?)
+    }
+
+    /// Return all of the teams and squads on the server.
+    ///
+    /// These are returned in one call by the server as per the squad rcon
+    /// protocol. This is one underlying rcon command.
+    pub async fn squads(&mut self) -> Result<(Vec<Team>, Vec<Squad>), Error> {
+        parse::squads(&self.raw_command(commands::LIST_SQUADS).await?)
+    }
+
+    /// Return a list of all the maps supported by the server
+    pub async fn list_maps(&mut self) -> Result<Vec<String>, Error> {
+        Ok(parse::list_maps(
+            &self.raw_command(commands::LIST_MAPS).await?,
+        ))
+    }
+
+    /// Return a list of all the maps supported by the server, as layers.
+    /// Names which are not recognised as layers are left out.
+    pub async fn list_maps_as_layers(&mut self) -> Result<Vec<Layer>, Error> {
+        Ok(parse::list_layers(
+            &self.raw_command(commands::LIST_MAPS).await?,
+        ))
+    }
+
+    /// Return a list of all the layers supported by the server
+    pub async fn list_layers(&mut self) -> Result<Vec<String>, Error> {
+        Ok(parse::list_maps(
+            &self.raw_command(commands::LIST_LAYERS).await?,
+        ))
+    }
+
+    /// End the current match
+    pub async fn end_match(&mut self) -> Result<AdminActionResult, Error> {
+        self.admin_command(Target::Server, commands::END_MATCH)
+            .await
+    }
+
+    /// Restart the current match
+    pub async fn restart_match(&mut self) -> Result<AdminActionResult, Error> {
+        self.admin_command(Target::Server, commands::RESTART_MATCH)
+            .await
+    }
+
+    /// Set the number of player slots, between 1 and 100
+    pub async fn set_max_num_players(
+        &mut self,
+        players: usize,
+    ) -> Result<AdminActionResult, Error> {
+        self.admin_command(Target::Server, commands::set_max_num_players(players)?)
+            .await
+    }
+
+    /// Set how many of the player slots are reserved
+    pub async fn set_num_reserved_slots(
+        &mut self,
+        slots: usize,
+    ) -> Result<AdminActionResult, Error> {
+        self.admin_command(Target::Server, commands::set_num_reserved_slots(slots)?)
+            .await
+    }
+
+    /// Set the password needed to join the server. An empty password removes
+    /// it.
+    pub async fn set_server_password<S: AsRef<str>>(
+        &mut self,
+        password: S,
+    ) -> Result<AdminActionResult, Error> {
+        self.admin_command(
+            Target::Server,
+            commands::set_server_password(password.as_ref())?,
+        )
+        .await
+    }
+
+    /// Set how many players can wait in the public queue. `-1` means no limit,
+    /// and `0` disables the queue.
+    pub async fn set_public_queue_limit(&mut self, limit: i32) -> Result<AdminActionResult, Error> {
+        self.admin_command(Target::Server, commands::set_public_queue_limit(limit)?)
+            .await
+    }
+
+    /// Set the speed of the game clock, where `1.0` is normal speed
+    pub async fn slomo(&mut self, scale: f32) -> Result<AdminActionResult, Error> {
+        self.admin_command(Target::Server, commands::slomo(scale)?)
+            .await
+    }
+
+    /// Switch a match rule on or off
+    pub async fn set_match_rule(
+        &mut self,
+        rule: MatchRule,
+        enabled: bool,
+    ) -> Result<AdminActionResult, Error> {
+        self.admin_command(Target::Server, commands::match_rule(rule, enabled)?)
+            .await
+    }
+
+    /// Make every role available, regardless of squad size or team limits
+    pub async fn force_all_role_availability(
+        &mut self,
+        enabled: bool,
+    ) -> Result<AdminActionResult, Error> {
+        self.set_match_rule(MatchRule::ForceAllRoleAvailability, enabled)
+            .await
+    }
+
+    /// Make every vehicle available, regardless of team limits
+    pub async fn force_all_vehicle_availability(
+        &mut self,
+        enabled: bool,
+    ) -> Result<AdminActionResult, Error> {
+        self.set_match_rule(MatchRule::ForceAllVehicleAvailability, enabled)
+            .await
+    }
+
+    /// Allow every deployable to be built, regardless of team limits
+    pub async fn force_all_deployable_availability(
+        &mut self,
+        enabled: bool,
+    ) -> Result<AdminActionResult, Error> {
+        self.set_match_rule(MatchRule::ForceAllDeployableAvailability, enabled)
+            .await
+    }
+
+    /// Let players respawn without waiting
+    pub async fn no_respawn_timer(&mut self, enabled: bool) -> Result<AdminActionResult, Error> {
+        self.set_match_rule(MatchRule::NoRespawnTimer, enabled)
+            .await
+    }
+
+    /// Let players change teams without waiting
+    pub async fn no_team_change_timer(
+        &mut self,
+        enabled: bool,
+    ) -> Result<AdminActionResult, Error> {
+        self.set_match_rule(MatchRule::NoTeamChangeTimer, enabled)
+            .await
+    }
+
+    /// Let vehicles be used without being claimed by a squad
+    pub async fn disable_vehicle_claiming(
+        &mut self,
+        enabled: bool,
+    ) -> Result<AdminActionResult, Error> {
+        self.set_match_rule(MatchRule::DisableVehicleClaiming, enabled)
+            .await
+    }
+
+    /// Set every rule of a preset, stopping at the first rule the server
+    /// rejects
+    pub async fn apply_preset(&mut self, preset: &Preset) -> Result<Vec<AdminActionResult>, Error> {
+        let mut results = Vec::new();
+        for command in preset.commands()? {
+            results.push(self.admin_command(Target::Server, command).await?);
+        }
+        Ok(results)
+    }
+
+    /// Set every rule of a preset back to the default, off
+    pub async fn revert_preset(
+        &mut self,
+        preset: &Preset,
+    ) -> Result<Vec<AdminActionResult>, Error> {
+        self.apply_preset(&preset.reverted()).await
+    }
+
+    /// Change the map currently running on the squad server.
+    pub async fn change_map<S: AsRef<str>>(&mut self, map: S) -> Result<AdminActionResult, Error> {
+        self.admin_command(Target::Layer, commands::change_map(map.as_ref())?)
+            .await
+    }
+
+    /// Change the layer currently running on the squad server.
+    pub async fn change_layer<S: AsRef<str>>(
+        &mut self,
+        layer: S,
+    ) -> Result<AdminActionResult, Error> {
+        self.admin_command(Target::Layer, commands::change_layer(layer.as_ref())?)
+            .await
+    }
+
+    /// Set the layer which will run on the squad server when the current game
+    /// is finished.
+    pub async fn set_next_layer<S: AsRef<str>>(
+        &mut self,
+        layer: S,
+    ) -> Result<AdminActionResult, Error> {
+        self.admin_command(Target::Layer, commands::set_next_layer(layer.as_ref())?)
+            .await
+    }
+
+    /// Set the map which will run on the squad server when the current game is
+    /// finished.
+    pub async fn set_next_map<S: AsRef<str>>(
+        &mut self,
+        map: S,
+    ) -> Result<AdminActionResult, Error> {
+        self.admin_command(Target::Layer, commands::set_next_map(map.as_ref())?)
+            .await
+    }
+
+    /// Force a player onto the other team.
+    ///
+    /// `name` can be player name or steam64id.
+    pub async fn force_team_change<S: AsRef<str>>(
+        &mut self,
+        name: S,
+    ) -> Result<AdminActionResult, Error> {
+        self.admin_command(Target::Player, commands::force_team_change(name.as_ref())?)
+            .await
+    }
+
+    /// Demote the commander.
+    ///
+    /// `name` can be player name or steam64id.
+    pub async fn demote_commander<S: AsRef<str>>(
+        &mut self,
+        name: S,
+    ) -> Result<AdminActionResult, Error> {
+        self.admin_command(Target::Player, commands::demote_commander(name.as_ref())?)
+            .await
+    }
+
+    /// Disband a squad.
+    pub async fn disband_squad(
+        &mut self,
+        team_id: usize,
+        squad_id: usize,
+    ) -> Result<AdminActionResult, Error> {
+        self.admin_command(Target::Server, commands::disband_squad(team_id, squad_id)?)
+            .await
+    }
+
+    /// Disband the given squad, using its team id and squad id
+    pub async fn disband(&mut self, squad: &Squad) -> Result<AdminActionResult, Error> {
+        self.disband_squad(squad.team_id(), squad.id()).await
+    }
+
+    /// Reset a squad's name to the default, such as "Squad 1"
+    pub async fn rename_squad(
+        &mut self,
+        team_id: usize,
+        squad_id: usize,
+    ) -> Result<AdminActionResult, Error> {
+        self.admin_command(Target::Server, commands::rename_squad(team_id, squad_id)?)
+            .await
+    }
+
+    /// Reset the given squad's name to the default
+    pub async fn reset_squad_name(&mut self, squad: &Squad) -> Result<AdminActionResult, Error> {
+        self.rename_squad(squad.team_id(), squad.id()).await
+    }
+
+    /// Reset the name of every squad for which `allowed` returns false, such
+    /// as squads named against server rules.
+    ///
+    /// Returns the squads which were renamed, with their old names.
+    pub async fn enforce_squad_names<F>(&mut self, allowed: F) -> Result<Vec<Squad>, Error>
+    where
+        F: Fn(&Squad) -> bool,
+    {
+        let (_, squads) = self.squads().await?;
+        let mut renamed = Vec::new();
+        for squad in squads.into_iter().filter(|squad| !allowed(squad)) {
+            self.reset_squad_name(&squad).await?;
+            renamed.push(squad);
+        }
+        Ok(renamed)
+    }
+
+    /// Remove a player from their squad.
+    ///
+    /// `name` can be player name or steam64id.
+    pub async fn remove_player_from_squad<S: AsRef<str>>(
+        &mut self,
+        name: S,
+    ) -> Result<AdminActionResult, Error> {
+        self.admin_command(
+            Target::Player,
+            commands::remove_player_from_squad(name.as_ref())?,
+        )
+        .await
+    }
+
+    /// Broadcast an administrative message to the server.
+    pub async fn broadcast<M>(&mut self, message: M) -> Result<AdminActionResult, Error>
+    where
+        M: AsRef<str>,
+    {
+        self.admin_command(Target::Message, commands::broadcast(message.as_ref())?)
+            .await
+    }
+
+    /// Send a message to admin chat, which only admins can see.
+    pub async fn chat_to_admin<M>(&mut self, message: M) -> Result<AdminActionResult, Error>
+    where
+        M: AsRef<str>,
+    {
+        self.admin_command(Target::Message, commands::chat_to_admin(message.as_ref())?)
+            .await
+    }
+
+    /// Warn a player by name or steamid
+    ///
+    /// `name` can be the player's name, or their steam64id
+    pub async fn warn<N, R>(&mut self, name: N, reason: R) -> Result<AdminActionResult, Error>
+    where
+        N: AsRef<str>,
+        R: AsRef<str>,
+    {
+        self.admin_command(
+            Target::Player,
+            commands::warn(name.as_ref(), reason.as_ref())?,
+        )
+        .await
+    }
+
+    /// Kick a player by name or steamid
+    ///
+    /// `name` can be the player's name, or their steam64id
+    pub async fn kick<N, R>(&mut self, name: N, reason: R) -> Result<AdminActionResult, Error>
+    where
+        N: AsRef<str>,
+        R: AsRef<str>,
+    {
+        self.admin_command(
+            Target::Player,
+            commands::kick(name.as_ref(), reason.as_ref())?,
+        )
+        .await
+    }
+
+    /// Ban a user for a diven amount of time
+    /// Length can be:
+    /// * 1d = one day
+    /// * 1m = one month
+    /// * 0 = permanent ban
+    ///
+    /// `name` can be the player's name, or their steam64id
+    pub async fn ban<N, L, R>(
+        &mut self,
+        name: N,
+        length: L,
+        reason: R,
+    ) -> Result<AdminActionResult, Error>
+    where
+        N: AsRef<str>,
+        L: AsRef<str>,
+        R: AsRef<str>,
+    {
+        self.admin_command(
+            Target::Player,
+            commands::ban(name.as_ref(), length.as_ref(), reason.as_ref())?,
+        )
+        .await
+    }
+
+    /// Warn a player by their id, or a `&Player`
+    pub async fn warn_by_id<I, R>(&mut self, id: I, reason: R) -> Result<AdminActionResult, Error>
+    where
+        I: Into<usize>,
+        R: AsRef<str>,
+    {
+        self.admin_command(
+            Target::Player,
+            commands::warn_by_id(id.into(), reason.as_ref())?,
+        )
+        .await
+    }
+
+    /// Kick a player by their id, or a `&Player`
+    pub async fn kick_by_id<I, R>(&mut self, id: I, reason: R) -> Result<AdminActionResult, Error>
+    where
+        I: Into<usize>,
+        R: AsRef<str>,
+    {
+        self.admin_command(
+            Target::Player,
+            commands::kick_by_id(id.into(), reason.as_ref())?,
+        )
+        .await
+    }
+
+    /// Ban a player by their id, or a `&Player`. `length` is as for `ban`.
+    pub async fn ban_by_id<I, L, R>(
+        &mut self,
+        id: I,
+        length: L,
+        reason: R,
+    ) -> Result<AdminActionResult, Error>
+    where
+        I: Into<usize>,
+        L: AsRef<str>,
+        R: AsRef<str>,
+    {
+        self.admin_command(
+            Target::Player,
+            commands::ban_by_id(id.into(), length.as_ref(), reason.as_ref())?,
+        )
+        .await
+    }
+
+    /// Move a player to the other team by their id, or a `&Player`
+    pub async fn force_team_change_by_id<I: Into<usize>>(
+        &mut self,
+        id: I,
+    ) -> Result<AdminActionResult, Error> {
+        self.admin_command(
+            Target::Player,
+            commands::force_team_change_by_id(id.into())?,
+        )
+        .await
+    }
+
+    /// Remove a player from their squad by their id, or a `&Player`
+    pub async fn remove_player_from_squad_by_id<I: Into<usize>>(
+        &mut self,
+        id: I,
+    ) -> Result<AdminActionResult, Error> {
+        self.admin_command(
+            Target::Player,
+            commands::remove_player_from_squad_by_id(id.into())?,
+        )
+        .await
+    }
+
+    /// Get the current map, and the next map
+    ///
+    /// Result is (current_map, next_map). `next_map` is empty if no next map
+    /// is set.
+    pub async fn maps(&mut self) -> Result<(String, String), Error> {
+        let (current, next) = self.layers().await?;
+        Ok(squad_rcon::maps(current, next))
+    }
+
+    /// Get the current layer, and the next layer if one is set, parsed into
+    /// `Layer`s
+    pub async fn maps_as_layers(&mut self) -> Result<(Layer, Option<Layer>), Error> {
+        let (current, next) = self.layers().await?;
+        parse::parsed_layers(&current, next.as_ref())
+    }
+
+    /// Get the current layer, and the next layer if one is set.
+    ///
+    /// The first call learns how the server reports layers, as with
+    /// `SquadRcon::layers`.
+    pub async fn layers(&mut self) -> Result<(LayerInfo, Option<LayerInfo>), Error> {
+        let current = match self.layer_format {
+            Some(LayerFormat::Layers) => Some(self.raw_command(commands::SHOW_CURRENT_MAP).await?),
+            _ => None,
+        };
+        let next = self.raw_command(commands::SHOW_NEXT_MAP).await?;
+        let current = match current {
+            Some(current) => current,
+            None => {
+                let layer_format = LayerFormat::detect(&next);
+                self.layer_format = Some(layer_format);
+                match layer_format {
+                    LayerFormat::Maps => String::new(),
+                    LayerFormat::Layers => self.raw_command(commands::SHOW_CURRENT_MAP).await?,
+                }
+            }
+        };
+        parse::layers(&current, &next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mock::fixtures::server;
+    use crate::AsyncSquadRcon;
+
+    #[tokio::test]
+    async fn async_players() {
+        let server = server();
+        let mut squad_rcon = AsyncSquadRcon::connect(server.addr(), "password")
+            .await
+            .unwrap();
+
+        server
+            .inject_chat("[ChatAll] [SteamID:76561198000000001] Alice : hello")
+            .unwrap();
+        assert_eq!(squad_rcon.players().await.unwrap().len(), 2);
+        assert_eq!(squad_rcon.take_chat_log().unwrap().len(), 1);
+    }
+}
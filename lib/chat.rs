@@ -11,10 +11,10 @@ pub struct Chat {
 impl Chat {
     pub fn new(channel: String, steam_id: String, name: String, message: String) -> Chat {
         Chat {
-            channel: channel,
-            steam_id: steam_id,
-            name: name,
-            message: message,
+            channel,
+            steam_id,
+            name,
+            message,
         }
     }
 
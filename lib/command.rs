@@ -0,0 +1,185 @@
+use crate::Error;
+
+/// One argument of a `Command`
+#[derive(Clone, Debug)]
+enum Argument {
+    /// Quoted only if it would otherwise be split or misread
+    Plain(String),
+    /// Always quoted
+    Quoted(String),
+    /// The rest of the line, as is
+    Text(String),
+}
+
+/// A builder for rcon commands, which quotes and escapes arguments so that
+/// player-controlled text, such as names, cannot add or change arguments.
+///
+/// Quoted arguments have `\` and `"` escaped with a backslash. No argument
+/// may contain control characters, such as newlines, as they would end the
+/// command early.
+///
+/// ```
+/// use squad_rcon::Command;
+///
+/// let command = Command::new("AdminKick")
+///     .quoted("foo\" bar")
+///     .text("Please change your name")
+///     .build()
+///     .unwrap();
+/// assert_eq!(command, r#"AdminKick "foo\" bar" Please change your name"#);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Command {
+    name: String,
+    arguments: Vec<Argument>,
+}
+
+impl Command {
+    pub fn new<S: Into<String>>(name: S) -> Command {
+        Command {
+            name: name.into(),
+            arguments: Vec::new(),
+        }
+    }
+
+    /// Add an argument, which is quoted if it is empty, or contains
+    /// whitespace, quotes or backslashes
+    pub fn arg<S: ToString>(mut self, argument: S) -> Command {
+        self.arguments.push(Argument::Plain(argument.to_string()));
+        self
+    }
+
+    /// Add an argument, which is always quoted
+    pub fn quoted<S: Into<String>>(mut self, argument: S) -> Command {
+        self.arguments.push(Argument::Quoted(argument.into()));
+        self
+    }
+
+    /// Add free text, such as a reason or a message, which the server reads
+    /// to the end of the line. This must be the last argument.
+    pub fn text<S: Into<String>>(mut self, text: S) -> Command {
+        self.arguments.push(Argument::Text(text.into()));
+        self
+    }
+
+    /// The command, as sent to the server.
+    ///
+    /// This fails with `Error::InvalidArgument` if any argument contains a
+    /// control character, or if text is followed by another argument.
+    pub fn build(&self) -> Result<String, Error> {
+        let mut command = validate(&self.name)?.to_string();
+        if command.is_empty() || command.contains(char::is_whitespace) {
+            return Err(Error::InvalidArgument(self.name.clone()));
+        }
+
+        for (i, argument) in self.arguments.iter().enumerate() {
+            command.push(' ');
+            match argument {
+                Argument::Plain(argument) => {
+                    let needs_quotes = argument.is_empty()
+                        || argument
+                            .chars()
+                            .any(|c| c.is_whitespace() || c == '"' || c == '\\');
+                    if needs_quotes {
+                        push_quoted(&mut command, validate(argument)?);
+                    } else {
+                        command.push_str(validate(argument)?);
+                    }
+                }
+                Argument::Quoted(argument) => push_quoted(&mut command, validate(argument)?),
+                Argument::Text(text) => {
+                    if i + 1 != self.arguments.len() {
+                        return Err(Error::InvalidArgument(text.clone()));
+                    }
+                    command.push_str(validate(text)?);
+                }
+            }
+        }
+
+        Ok(command.trim_end().to_string())
+    }
+}
+
+/// Reject arguments containing control characters
+fn validate(argument: &str) -> Result<&str, Error> {
+    if argument.chars().any(char::is_control) {
+        Err(Error::InvalidArgument(argument.to_string()))
+    } else {
+        Ok(argument)
+    }
+}
+
+fn push_quoted(command: &mut String, argument: &str) {
+    command.push('"');
+    for c in argument.chars() {
+        if c == '"' || c == '\\' {
+            command.push('\\');
+        }
+        command.push(c);
+    }
+    command.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Command;
+    use crate::mock::fixtures::{exact_config, server};
+    use crate::{Error, SquadRcon};
+
+    #[test]
+    fn plain_arguments() {
+        let command = Command::new("AdminDisbandSquad").arg(1).arg(2);
+        assert_eq!(command.build().unwrap(), "AdminDisbandSquad 1 2");
+
+        let command = Command::new("AdminChangeMap").arg("Narva RAAS");
+        assert_eq!(command.build().unwrap(), r#"AdminChangeMap "Narva RAAS""#);
+    }
+
+    #[test]
+    fn quoting() {
+        let command = Command::new("AdminWarn").quoted(r#"a\" b"#).text("stop");
+        assert_eq!(command.build().unwrap(), r#"AdminWarn "a\\\" b" stop"#);
+
+        let command = Command::new("AdminWarn").quoted("").text("");
+        assert_eq!(command.build().unwrap(), r#"AdminWarn """#);
+    }
+
+    #[test]
+    fn control_characters() {
+        for command in [
+            Command::new("AdminKick").quoted("Bob\nAdminEndMatch"),
+            Command::new("AdminKick")
+                .quoted("Bob")
+                .text("bye\r\nAdminEndMatch"),
+            Command::new("AdminBroadcast").text("a\0b"),
+        ] {
+            match command.build() {
+                Err(Error::InvalidArgument(_)) => {}
+                other => panic!("{:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn text_must_be_last() {
+        let command = Command::new("AdminBan").text("reason").quoted("1d");
+        assert!(command.build().is_err());
+    }
+
+    #[test]
+    fn command_injection() {
+        let server = server();
+        let mut squad_rcon =
+            SquadRcon::connect_with_config(server.addr(), "password", exact_config()).unwrap();
+
+        squad_rcon.warn("Bob\" AdminEndMatch", "hi").unwrap();
+        match squad_rcon.kick("Bob", "bye\nAdminEndMatch") {
+            Err(Error::InvalidArgument(_)) => {}
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(
+            server.received_commands(),
+            vec![r#"AdminWarn "Bob\" AdminEndMatch" hi"#]
+        );
+    }
+}
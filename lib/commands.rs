@@ -0,0 +1,164 @@
+//! Formatting of the raw commands sent to the squad server.
+//!
+//! These are shared between `SquadRcon` and `AsyncSquadRcon`. Every command
+//! with arguments is built with `Command`, so arguments are quoted and
+//! escaped.
+
+use crate::{Command, Error, MatchRule};
+
+pub const LIST_PLAYERS: &str = "ListPlayers";
+pub const LIST_SQUADS: &str = "ListSquads";
+pub const LIST_MAPS: &str = "ListMaps";
+pub const SHOW_NEXT_MAP: &str = "ShowNextMap";
+pub const SHOW_CURRENT_MAP: &str = "ShowCurrentMap";
+pub const LIST_LAYERS: &str = "ListLayers";
+pub const END_MATCH: &str = "AdminEndMatch";
+pub const RESTART_MATCH: &str = "AdminRestartMatch";
+
+/// The most players a squad server can hold
+pub const MAX_PLAYERS: usize = 100;
+
+pub fn change_map(map: &str) -> Result<String, Error> {
+    Command::new("AdminChangeMap").arg(map).build()
+}
+
+pub fn set_next_map(map: &str) -> Result<String, Error> {
+    Command::new("AdminSetNextMap").arg(map).build()
+}
+
+pub fn change_layer(layer: &str) -> Result<String, Error> {
+    Command::new("AdminChangeLayer").arg(layer).build()
+}
+
+pub fn set_next_layer(layer: &str) -> Result<String, Error> {
+    Command::new("AdminSetNextLayer").arg(layer).build()
+}
+
+pub fn force_team_change(name: &str) -> Result<String, Error> {
+    Command::new("AdminForceTeamChange").arg(name).build()
+}
+
+pub fn demote_commander(name: &str) -> Result<String, Error> {
+    Command::new("AdminDemoteCommander").arg(name).build()
+}
+
+pub fn disband_squad(team_id: usize, squad_id: usize) -> Result<String, Error> {
+    Command::new("AdminDisbandSquad")
+        .arg(team_id)
+        .arg(squad_id)
+        .build()
+}
+
+pub fn rename_squad(team_id: usize, squad_id: usize) -> Result<String, Error> {
+    Command::new("AdminRenameSquad")
+        .arg(team_id)
+        .arg(squad_id)
+        .build()
+}
+
+pub fn remove_player_from_squad(name: &str) -> Result<String, Error> {
+    Command::new("AdminRemovePlayerFromSquad").arg(name).build()
+}
+
+pub fn broadcast(message: &str) -> Result<String, Error> {
+    Command::new("AdminBroadcast").text(message).build()
+}
+
+pub fn chat_to_admin(message: &str) -> Result<String, Error> {
+    Command::new("ChatToAdmin").text(message).build()
+}
+
+pub fn warn(name: &str, reason: &str) -> Result<String, Error> {
+    Command::new("AdminWarn").quoted(name).text(reason).build()
+}
+
+pub fn kick(name: &str, reason: &str) -> Result<String, Error> {
+    Command::new("AdminKick").quoted(name).text(reason).build()
+}
+
+pub fn ban(name: &str, length: &str, reason: &str) -> Result<String, Error> {
+    Command::new("AdminBan")
+        .quoted(name)
+        .quoted(length)
+        .text(reason)
+        .build()
+}
+
+pub fn warn_by_id(id: usize, reason: &str) -> Result<String, Error> {
+    Command::new("AdminWarnById").arg(id).text(reason).build()
+}
+
+pub fn kick_by_id(id: usize, reason: &str) -> Result<String, Error> {
+    Command::new("AdminKickById").arg(id).text(reason).build()
+}
+
+pub fn ban_by_id(id: usize, length: &str, reason: &str) -> Result<String, Error> {
+    Command::new("AdminBanById")
+        .arg(id)
+        .quoted(length)
+        .text(reason)
+        .build()
+}
+
+pub fn force_team_change_by_id(id: usize) -> Result<String, Error> {
+    Command::new("AdminForceTeamChangeById").arg(id).build()
+}
+
+pub fn remove_player_from_squad_by_id(id: usize) -> Result<String, Error> {
+    Command::new("AdminRemovePlayerFromSquadById")
+        .arg(id)
+        .build()
+}
+
+pub fn set_max_num_players(players: usize) -> Result<String, Error> {
+    if players == 0 || players > MAX_PLAYERS {
+        return Err(Error::InvalidArgument(players.to_string()));
+    }
+    Command::new("AdminSetMaxNumPlayers").arg(players).build()
+}
+
+pub fn set_num_reserved_slots(slots: usize) -> Result<String, Error> {
+    if slots > MAX_PLAYERS {
+        return Err(Error::InvalidArgument(slots.to_string()));
+    }
+    Command::new("AdminSetNumReservedSlots").arg(slots).build()
+}
+
+/// An empty password removes the server's password. Passwords may not
+/// contain whitespace, quotes or backslashes, which the server would not
+/// read back as sent.
+pub fn set_server_password(password: &str) -> Result<String, Error> {
+    if password
+        .chars()
+        .any(|c| c.is_whitespace() || c == '"' || c == '\\')
+    {
+        return Err(Error::InvalidArgument(password.to_string()));
+    }
+    let command = Command::new("AdminSetServerPassword");
+    if password.is_empty() {
+        command.build()
+    } else {
+        command.arg(password).build()
+    }
+}
+
+/// A limit of `-1` means the public queue is unlimited, and `0` disables it
+pub fn set_public_queue_limit(limit: i32) -> Result<String, Error> {
+    if limit < -1 {
+        return Err(Error::InvalidArgument(limit.to_string()));
+    }
+    Command::new("AdminSetPublicQueueLimit").arg(limit).build()
+}
+
+pub fn slomo(scale: f32) -> Result<String, Error> {
+    if !scale.is_finite() || scale <= 0.0 {
+        return Err(Error::InvalidArgument(scale.to_string()));
+    }
+    Command::new("AdminSlomo").arg(scale).build()
+}
+
+pub fn match_rule(rule: MatchRule, enabled: bool) -> Result<String, Error> {
+    Command::new(rule.command_name())
+        .arg(if enabled { 1 } else { 0 })
+        .build()
+}
@@ -0,0 +1,136 @@
+use crate::rcon::DEFAULT_MAX_BODY_LENGTH;
+use crate::Terminator;
+use std::time::Duration;
+
+/// Settings for an rcon connection.
+///
+/// By default nothing times out, as with a plain `TcpStream`. Setting a read
+/// timeout is recommended, so a server which stops answering is noticed.
+#[derive(Clone, Debug)]
+pub struct RconConfig {
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    heartbeat_interval: Option<Duration>,
+    command_timeout: Option<Duration>,
+    max_body_length: usize,
+    terminator: Terminator,
+}
+
+impl Default for RconConfig {
+    fn default() -> RconConfig {
+        RconConfig {
+            connect_timeout: None,
+            read_timeout: None,
+            write_timeout: None,
+            heartbeat_interval: None,
+            command_timeout: None,
+            max_body_length: DEFAULT_MAX_BODY_LENGTH,
+            terminator: Terminator::ShowNextMap,
+        }
+    }
+}
+
+impl RconConfig {
+    pub fn new() -> RconConfig {
+        RconConfig::default()
+    }
+
+    /// How long to wait for the TCP connection to be established, or `None`
+    /// to wait forever
+    pub fn with_connect_timeout(mut self, connect_timeout: Option<Duration>) -> RconConfig {
+        self.connect_timeout = connect_timeout;
+        self
+    }
+
+    /// How long to wait for the server while waiting on a response, or `None`
+    /// to wait forever
+    pub fn with_read_timeout(mut self, read_timeout: Option<Duration>) -> RconConfig {
+        self.read_timeout = read_timeout;
+        self
+    }
+
+    /// How long to wait for the server to accept a command, or `None` to
+    /// wait forever
+    pub fn with_write_timeout(mut self, write_timeout: Option<Duration>) -> RconConfig {
+        self.write_timeout = write_timeout;
+        self
+    }
+
+    /// How long a threaded connection may go without hearing from the server
+    /// before it is probed. If the probe is not answered within the read
+    /// timeout, the connection is considered dead, and commands fail with
+    /// `Error::Timeout`. `None` disables the heartbeat.
+    pub fn with_heartbeat_interval(mut self, heartbeat_interval: Option<Duration>) -> RconConfig {
+        self.heartbeat_interval = heartbeat_interval;
+        self
+    }
+
+    /// How long to wait for the whole response to a command, or `None` to
+    /// wait as long as the server keeps sending within the read timeout.
+    ///
+    /// Commands which are not answered in time fail with
+    /// `Error::CommandTimeout`, and the connection remains usable.
+    pub fn with_command_timeout(mut self, command_timeout: Option<Duration>) -> RconConfig {
+        self.command_timeout = command_timeout;
+        self
+    }
+
+    /// The longest packet body accepted from the server. Larger packets fail
+    /// with `Error::PacketTooLarge`, and the connection should be considered
+    /// unusable.
+    pub fn with_max_body_length(mut self, max_body_length: usize) -> RconConfig {
+        self.max_body_length = max_body_length;
+        self
+    }
+
+    /// How the end of each response is recognised. This is
+    /// `Terminator::ShowNextMap` by default, which works with every server.
+    pub fn with_terminator(mut self, terminator: Terminator) -> RconConfig {
+        self.terminator = terminator;
+        self
+    }
+
+    pub fn connect_timeout(&self) -> Option<Duration> {
+        self.connect_timeout
+    }
+    pub fn read_timeout(&self) -> Option<Duration> {
+        self.read_timeout
+    }
+    pub fn write_timeout(&self) -> Option<Duration> {
+        self.write_timeout
+    }
+    pub fn heartbeat_interval(&self) -> Option<Duration> {
+        self.heartbeat_interval
+    }
+    pub fn command_timeout(&self) -> Option<Duration> {
+        self.command_timeout
+    }
+    pub fn max_body_length(&self) -> usize {
+        self.max_body_length
+    }
+    pub fn terminator(&self) -> Terminator {
+        self.terminator
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mock::fixtures::server;
+    use crate::{Error, RconConfig, SquadRcon};
+    use std::time::Duration;
+
+    #[test]
+    fn read_timeout() {
+        let server = server();
+        let config = RconConfig::new().with_read_timeout(Some(Duration::from_millis(100)));
+        let mut squad_rcon =
+            SquadRcon::connect_with_config(server.addr(), "password", config).unwrap();
+
+        server.set_silent(true);
+        match squad_rcon.players() {
+            Err(Error::Timeout) => {}
+            other => panic!("expected Timeout, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// A player who left the server recently, as listed under "Recently
+/// Disconnected Players" by `ListPlayers`
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DisconnectedPlayer {
+    id: usize,
+    steam_id: String,
+    since_disconnect: Duration,
+    name: String,
+    eos_id: Option<String>,
+}
+
+impl DisconnectedPlayer {
+    pub fn new(
+        id: usize,
+        steam_id: String,
+        since_disconnect: Duration,
+        name: String,
+    ) -> DisconnectedPlayer {
+        DisconnectedPlayer {
+            id,
+            steam_id,
+            since_disconnect,
+            name,
+            eos_id: None,
+        }
+    }
+
+    pub fn with_eos_id<S: Into<String>>(mut self, eos_id: S) -> DisconnectedPlayer {
+        self.eos_id = Some(eos_id.into());
+        self
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+    /// The player's steam64id. This is empty for players the server only
+    /// lists with an EOS id.
+    pub fn steam_id(&self) -> &str {
+        &self.steam_id
+    }
+    /// How long ago the player disconnected, when the list was fetched
+    pub fn since_disconnect(&self) -> Duration {
+        self.since_disconnect
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    /// The player's Epic Online Services id, on servers which list it
+    pub fn eos_id(&self) -> Option<&str> {
+        self.eos_id.as_deref()
+    }
+}
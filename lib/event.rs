@@ -0,0 +1,242 @@
+use crate::{parse, Chat};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+lazy_static! {
+    static ref ADMIN_CAMERA_REGEX: Regex =
+        Regex::new(r"^\[SteamID:(\d*)\] (.*?) has (un)?possessed admin camera\.?$")
+            .expect("ADMIN_CAMERA_REGEX");
+    pub(crate) static ref WARNED_REGEX: Regex =
+        Regex::new(r#"^Remote admin has warned player (.*?)\. Message was "(.*)"$"#)
+            .expect("WARNED_REGEX");
+    pub(crate) static ref KICKED_REGEX: Regex =
+        Regex::new(r"^Kicked player (\d*)\. \[steamid=(\d*)\] (.*)$").expect("KICKED_REGEX");
+    pub(crate) static ref BANNED_REGEX: Regex =
+        Regex::new(r"^Banned player (\d*)\. \[steamid=(\d*)\] (.*) for interval (.*)$")
+            .expect("BANNED_REGEX");
+    static ref SQUAD_CREATED_REGEX: Regex = Regex::new(
+        r"^(.*) \(Steam ID: (\d*)\) has created Squad (\d*) \(Squad Name: (.*)\) on (.*)$"
+    )
+    .expect("SQUAD_CREATED_REGEX");
+}
+
+/// A message sent by the server without being asked for, as the body of a
+/// SERVERDATA_CHAT packet.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum ServerEvent {
+    /// A player sent a chat message
+    Chat(Chat),
+    /// An admin entered the admin camera
+    AdminCameraEntered { steam_id: String, name: String },
+    /// An admin left the admin camera
+    AdminCameraLeft { steam_id: String, name: String },
+    /// A player was warned by an admin
+    Warned { name: String, message: String },
+    /// A player was kicked from the server
+    Kicked {
+        id: usize,
+        steam_id: String,
+        name: String,
+    },
+    /// A player was banned from the server
+    Banned {
+        id: usize,
+        steam_id: String,
+        name: String,
+        interval: String,
+    },
+    /// A player created a squad
+    SquadCreated {
+        name: String,
+        steam_id: String,
+        squad_id: usize,
+        squad_name: String,
+        team_name: String,
+    },
+    /// A message this library does not understand, as sent by the server
+    Unknown(String),
+}
+
+impl ServerEvent {
+    /// Parse the body of a SERVERDATA_CHAT packet.
+    ///
+    /// This never fails. Messages which are not understood are returned as
+    /// `ServerEvent::Unknown`.
+    pub fn parse(body: &str) -> ServerEvent {
+        if let Ok(chat) = parse::chat(body) {
+            ServerEvent::Chat(chat)
+        } else if let Some(captures) = ADMIN_CAMERA_REGEX.captures(body) {
+            let steam_id = captures[1].to_string();
+            let name = captures[2].to_string();
+            if captures.get(3).is_some() {
+                ServerEvent::AdminCameraLeft { steam_id, name }
+            } else {
+                ServerEvent::AdminCameraEntered { steam_id, name }
+            }
+        } else if let Some(captures) = WARNED_REGEX.captures(body) {
+            ServerEvent::Warned {
+                name: captures[1].to_string(),
+                message: captures[2].to_string(),
+            }
+        } else if let Some(captures) = KICKED_REGEX.captures(body) {
+            match captures[1].parse() {
+                Ok(id) => ServerEvent::Kicked {
+                    id,
+                    steam_id: captures[2].to_string(),
+                    name: captures[3].to_string(),
+                },
+                Err(_) => ServerEvent::Unknown(body.to_string()),
+            }
+        } else if let Some(captures) = BANNED_REGEX.captures(body) {
+            match captures[1].parse() {
+                Ok(id) => ServerEvent::Banned {
+                    id,
+                    steam_id: captures[2].to_string(),
+                    name: captures[3].to_string(),
+                    interval: captures[4].to_string(),
+                },
+                Err(_) => ServerEvent::Unknown(body.to_string()),
+            }
+        } else if let Some(captures) = SQUAD_CREATED_REGEX.captures(body) {
+            match captures[3].parse() {
+                Ok(squad_id) => ServerEvent::SquadCreated {
+                    name: captures[1].to_string(),
+                    steam_id: captures[2].to_string(),
+                    squad_id,
+                    squad_name: captures[4].to_string(),
+                    team_name: captures[5].to_string(),
+                },
+                Err(_) => ServerEvent::Unknown(body.to_string()),
+            }
+        } else {
+            ServerEvent::Unknown(body.to_string())
+        }
+    }
+
+    /// The chat message, if this event is one
+    pub fn chat(&self) -> Option<&Chat> {
+        match self {
+            ServerEvent::Chat(chat) => Some(chat),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ServerEvent;
+    use crate::mock::fixtures::server;
+    use crate::SquadRcon;
+
+    #[test]
+    fn chat() {
+        match ServerEvent::parse("[ChatTeam] [SteamID:76561198000000001] Alice : need ammo") {
+            ServerEvent::Chat(chat) => {
+                assert_eq!(chat.channel(), "ChatTeam");
+                assert_eq!(chat.message(), "need ammo");
+            }
+            event => panic!("{:?}", event),
+        }
+    }
+
+    #[test]
+    fn admin_camera() {
+        match ServerEvent::parse("[SteamID:76561198000000001] Alice has possessed admin camera.") {
+            ServerEvent::AdminCameraEntered { steam_id, name } => {
+                assert_eq!(steam_id, "76561198000000001");
+                assert_eq!(name, "Alice");
+            }
+            event => panic!("{:?}", event),
+        }
+        match ServerEvent::parse("[SteamID:76561198000000001] Alice has unpossessed admin camera.")
+        {
+            ServerEvent::AdminCameraLeft { name, .. } => assert_eq!(name, "Alice"),
+            event => panic!("{:?}", event),
+        }
+    }
+
+    #[test]
+    fn warned() {
+        match ServerEvent::parse(
+            "Remote admin has warned player Bob. Message was \"stop teamkilling\"",
+        ) {
+            ServerEvent::Warned { name, message } => {
+                assert_eq!(name, "Bob");
+                assert_eq!(message, "stop teamkilling");
+            }
+            event => panic!("{:?}", event),
+        }
+    }
+
+    #[test]
+    fn kicked_and_banned() {
+        match ServerEvent::parse("Kicked player 3. [steamid=76561198000000002] Bob") {
+            ServerEvent::Kicked { id, steam_id, name } => {
+                assert_eq!(id, 3);
+                assert_eq!(steam_id, "76561198000000002");
+                assert_eq!(name, "Bob");
+            }
+            event => panic!("{:?}", event),
+        }
+        match ServerEvent::parse("Banned player 3. [steamid=76561198000000002] Bob for interval 1d")
+        {
+            ServerEvent::Banned { id, interval, .. } => {
+                assert_eq!(id, 3);
+                assert_eq!(interval, "1d");
+            }
+            event => panic!("{:?}", event),
+        }
+    }
+
+    #[test]
+    fn squad_created() {
+        match ServerEvent::parse(
+            "Alice (Steam ID: 76561198000000001) has created Squad 4 (Squad Name: INF) on United States Army",
+        ) {
+            ServerEvent::SquadCreated {
+                name,
+                squad_id,
+                squad_name,
+                team_name,
+                ..
+            } => {
+                assert_eq!(name, "Alice");
+                assert_eq!(squad_id, 4);
+                assert_eq!(squad_name, "INF");
+                assert_eq!(team_name, "United States Army");
+            }
+            event => panic!("{:?}", event),
+        }
+    }
+
+    #[test]
+    fn unknown() {
+        match ServerEvent::parse("Something new happened") {
+            ServerEvent::Unknown(body) => assert_eq!(body, "Something new happened"),
+            event => panic!("{:?}", event),
+        }
+    }
+
+    #[test]
+    fn events() {
+        let server = server();
+        let mut squad_rcon = SquadRcon::connect(server.addr(), "password").unwrap();
+
+        server
+            .inject_chat("Kicked player 1. [steamid=76561198000000002] Bob")
+            .unwrap();
+        server
+            .inject_chat("[ChatAll] [SteamID:76561198000000001] Alice : hello")
+            .unwrap();
+        squad_rcon.maps().unwrap();
+
+        let events = squad_rcon.take_events();
+        assert_eq!(events.len(), 2);
+        match &events[0] {
+            ServerEvent::Kicked { name, .. } => assert_eq!(name, "Bob"),
+            event => panic!("{:?}", event),
+        }
+        assert_eq!(events[1].chat().unwrap().name(), "Alice");
+    }
+}
@@ -0,0 +1,527 @@
+use crate::rate_limit::TokenBucket;
+use crate::transport::{TcpTransport, Transport};
+use crate::{
+    AdminActionResult, Error, Player, Priority, RateLimit, RconConfig, ServerEvent, Snapshot, Squad,
+};
+use crate::{LayerInfo, PlayerList, SquadRcon, Team};
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::net::ToSocketAddrs;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// A job run by the actor thread against the connection it owns
+type Job<T> = Box<dyn FnOnce(&mut SquadRcon<T>) + Send>;
+
+/// The receivers of events, shared by every handle and the actor
+type Subscribers = Arc<Mutex<Vec<Sender<ServerEvent>>>>;
+
+/// A job waiting for its turn
+struct Queued<T: Transport> {
+    priority: Priority,
+    /// Jobs of the same priority run in the order they were queued
+    sequence: Reverse<u64>,
+    job: Job<T>,
+}
+
+impl<T: Transport> PartialEq for Queued<T> {
+    fn eq(&self, other: &Queued<T>) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T: Transport> Eq for Queued<T> {}
+
+impl<T: Transport> PartialOrd for Queued<T> {
+    fn partial_cmp(&self, other: &Queued<T>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: Transport> Ord for Queued<T> {
+    fn cmp(&self, other: &Queued<T>) -> Ordering {
+        (self.priority, self.sequence).cmp(&(other.priority, other.sequence))
+    }
+}
+
+/// A cloneable handle to a `SquadRcon` owned by a dedicated actor thread.
+///
+/// Commands from every clone are queued, and run one at a time by the actor,
+/// and each result is returned to the caller which sent it. No lock is held
+/// while waiting, so a caller is only blocked by the commands queued before
+/// its own.
+///
+/// With a `RateLimit`, commands wait in a queue until the limit allows them
+/// to be sent, and higher `Priority` commands are sent first.
+///
+/// Events from the server are broadcast to every `Receiver` returned by
+/// `subscribe`. The actor exits once every handle has been dropped.
+pub struct SquadRconHandle<T: Transport = TcpTransport> {
+    jobs: Sender<(Priority, Job<T>)>,
+    subscribers: Subscribers,
+}
+
+impl<T: Transport> Clone for SquadRconHandle<T> {
+    fn clone(&self) -> SquadRconHandle<T> {
+        SquadRconHandle {
+            jobs: self.jobs.clone(),
+            subscribers: self.subscribers.clone(),
+        }
+    }
+}
+
+impl SquadRconHandle<TcpTransport> {
+    pub fn connect<A: ToSocketAddrs, S: Into<String>>(
+        addr: A,
+        password: S,
+    ) -> Result<SquadRconHandle, Error> {
+        SquadRconHandle::connect_with_config(addr, password, RconConfig::default())
+    }
+
+    /// Connect with the given timeouts and settings
+    pub fn connect_with_config<A: ToSocketAddrs, S: Into<String>>(
+        addr: A,
+        password: S,
+        config: RconConfig,
+    ) -> Result<SquadRconHandle, Error> {
+        let (squad_rcon, event_receiver) =
+            SquadRcon::connect_threaded_with_config(addr, password, config)?;
+        Ok(SquadRconHandle::new(squad_rcon, Some(event_receiver)))
+    }
+
+    /// Connect to `host`, which is resolved again each time the connection
+    /// is re-established, as with `SquadRcon::connect_host`
+    pub fn connect_host<H: Into<String>, S: Into<String>>(
+        host: H,
+        password: S,
+        config: RconConfig,
+    ) -> Result<SquadRconHandle, Error> {
+        let (squad_rcon, event_receiver) =
+            SquadRcon::connect_host_threaded(host, password, config)?;
+        Ok(SquadRconHandle::new(squad_rcon, Some(event_receiver)))
+    }
+}
+
+impl<T: Transport> SquadRconHandle<T> {
+    /// Start the actor thread for a connection.
+    ///
+    /// For a threaded connection, pass the `Receiver` returned by
+    /// `SquadRcon::connect_threaded`, and events are broadcast as they
+    /// arrive. Otherwise pass `None`, and the events picked up while running
+    /// commands are broadcast after each command.
+    pub fn new(
+        squad_rcon: SquadRcon<T>,
+        event_receiver: Option<Receiver<ServerEvent>>,
+    ) -> SquadRconHandle<T> {
+        SquadRconHandle::with_rate_limit(squad_rcon, event_receiver, None)
+    }
+
+    /// Start the actor thread, sending commands no faster than `rate_limit`
+    /// allows, or as fast as the server answers if it is `None`
+    pub fn with_rate_limit(
+        squad_rcon: SquadRcon<T>,
+        event_receiver: Option<Receiver<ServerEvent>>,
+        rate_limit: Option<RateLimit>,
+    ) -> SquadRconHandle<T> {
+        let (jobs, job_receiver) = channel();
+        let subscribers: Subscribers = Arc::new(Mutex::new(Vec::new()));
+
+        let actor_subscribers = subscribers.clone();
+        let token_bucket = rate_limit.map(TokenBucket::new);
+        thread::spawn(move || run(squad_rcon, job_receiver, actor_subscribers, token_bucket));
+
+        if let Some(event_receiver) = event_receiver {
+            let broadcast_subscribers = subscribers.clone();
+            thread::spawn(move || {
+                for event in event_receiver {
+                    broadcast(&broadcast_subscribers, event);
+                }
+            });
+        }
+
+        SquadRconHandle { jobs, subscribers }
+    }
+
+    /// Receive every event from the server from now on
+    pub fn subscribe(&self) -> Receiver<ServerEvent> {
+        let (sender, receiver) = channel();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// Run `f` on the actor thread at `Priority::Query`, and wait for its
+    /// result.
+    ///
+    /// This is how any `SquadRcon` method without a wrapper here is called.
+    pub fn call<F, R>(&self, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&mut SquadRcon<T>) -> Result<R, Error> + Send + 'static,
+        R: Send + 'static,
+    {
+        self.call_with_priority(Priority::Query, f)
+    }
+
+    /// Run `f` on the actor thread at the given priority, and wait for its
+    /// result.
+    ///
+    /// Every command `f` sends is charged to the rate limit. `f` only waits
+    /// for the first of them, and the calls after it wait for the rest.
+    ///
+    /// If `f` panics, the panic is resumed on the calling thread, and the
+    /// actor carries on with the next job.
+    pub fn call_with_priority<F, R>(&self, priority: Priority, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&mut SquadRcon<T>) -> Result<R, Error> + Send + 'static,
+        R: Send + 'static,
+    {
+        let (sender, receiver) = channel();
+        let job: Job<T> = Box::new(move |squad_rcon| {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| f(squad_rcon)));
+            // The caller may have given up waiting
+            sender.send(result).ok();
+        });
+        self.jobs
+            .send((priority, job))
+            .map_err(|_| Error::Disconnected)?;
+        match receiver.recv().map_err(|_| Error::Disconnected)? {
+            Ok(result) => result,
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+
+    /// See `SquadRcon::raw_command`. This runs at `Priority::Query`.
+    pub fn raw_command<S: Into<String>>(&self, command: S) -> Result<String, Error> {
+        let command = command.into();
+        self.call(move |squad_rcon| squad_rcon.raw_command(command))
+    }
+
+    /// See `SquadRcon::broadcast`. This runs at `Priority::Informational`.
+    pub fn broadcast<M: Into<String>>(&self, message: M) -> Result<AdminActionResult, Error> {
+        let message = message.into();
+        self.call_with_priority(Priority::Informational, move |squad_rcon| {
+            squad_rcon.broadcast(message)
+        })
+    }
+
+    /// See `SquadRcon::warn`. This runs at `Priority::Moderation`.
+    pub fn warn<N: Into<String>, R: Into<String>>(
+        &self,
+        name: N,
+        reason: R,
+    ) -> Result<AdminActionResult, Error> {
+        let (name, reason) = (name.into(), reason.into());
+        self.call_with_priority(Priority::Moderation, move |squad_rcon| {
+            squad_rcon.warn(name, reason)
+        })
+    }
+
+    /// See `SquadRcon::kick`. This runs at `Priority::Moderation`.
+    pub fn kick<N: Into<String>, R: Into<String>>(
+        &self,
+        name: N,
+        reason: R,
+    ) -> Result<AdminActionResult, Error> {
+        let (name, reason) = (name.into(), reason.into());
+        self.call_with_priority(Priority::Moderation, move |squad_rcon| {
+            squad_rcon.kick(name, reason)
+        })
+    }
+
+    /// See `SquadRcon::ban`. This runs at `Priority::Moderation`.
+    pub fn ban<N, L, R>(&self, name: N, length: L, reason: R) -> Result<AdminActionResult, Error>
+    where
+        N: Into<String>,
+        L: Into<String>,
+        R: Into<String>,
+    {
+        let (name, length, reason) = (name.into(), length.into(), reason.into());
+        self.call_with_priority(Priority::Moderation, move |squad_rcon| {
+            squad_rcon.ban(name, length, reason)
+        })
+    }
+
+    /// See `SquadRcon::warn_by_id`. This runs at `Priority::Moderation`.
+    pub fn warn_by_id<I: Into<usize>, R: Into<String>>(
+        &self,
+        id: I,
+        reason: R,
+    ) -> Result<AdminActionResult, Error> {
+        let (id, reason) = (id.into(), reason.into());
+        self.call_with_priority(Priority::Moderation, move |squad_rcon| {
+            squad_rcon.warn_by_id(id, reason)
+        })
+    }
+
+    /// See `SquadRcon::kick_by_id`. This runs at `Priority::Moderation`.
+    pub fn kick_by_id<I: Into<usize>, R: Into<String>>(
+        &self,
+        id: I,
+        reason: R,
+    ) -> Result<AdminActionResult, Error> {
+        let (id, reason) = (id.into(), reason.into());
+        self.call_with_priority(Priority::Moderation, move |squad_rcon| {
+            squad_rcon.kick_by_id(id, reason)
+        })
+    }
+
+    /// See `SquadRcon::ban_by_id`. This runs at `Priority::Moderation`.
+    pub fn ban_by_id<I, L, R>(
+        &self,
+        id: I,
+        length: L,
+        reason: R,
+    ) -> Result<AdminActionResult, Error>
+    where
+        I: Into<usize>,
+        L: Into<String>,
+        R: Into<String>,
+    {
+        let (id, length, reason) = (id.into(), length.into(), reason.into());
+        self.call_with_priority(Priority::Moderation, move |squad_rcon| {
+            squad_rcon.ban_by_id(id, length, reason)
+        })
+    }
+
+    /// See `SquadRcon::players`
+    pub fn players(&self) -> Result<Vec<Player>, Error> {
+        self.call(|squad_rcon| squad_rcon.players())
+    }
+
+    /// See `SquadRcon::player_list`
+    pub fn player_list(&self) -> Result<PlayerList, Error> {
+        self.call(|squad_rcon| squad_rcon.player_list())
+    }
+
+    /// See `SquadRcon::squads`
+    pub fn squads(&self) -> Result<(Vec<Team>, Vec<Squad>), Error> {
+        self.call(|squad_rcon| squad_rcon.squads())
+    }
+
+    /// See `SquadRcon::maps`
+    pub fn maps(&self) -> Result<(String, String), Error> {
+        self.call(|squad_rcon| squad_rcon.maps())
+    }
+
+    /// See `SquadRcon::layers`
+    pub fn layers(&self) -> Result<(LayerInfo, Option<LayerInfo>), Error> {
+        self.call(|squad_rcon| squad_rcon.layers())
+    }
+
+    /// See `SquadRcon::snapshot`
+    pub fn snapshot(&self) -> Result<Snapshot, Error> {
+        self.call(|squad_rcon| squad_rcon.snapshot())
+    }
+}
+
+/// Send an event to every subscriber, forgetting those whose receiver has
+/// been dropped
+fn broadcast(subscribers: &Subscribers, event: ServerEvent) {
+    subscribers
+        .lock()
+        .unwrap()
+        .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+}
+
+/// The actor: run queued jobs, highest priority first, as the rate limit
+/// allows, until every handle has been dropped
+fn run<T: Transport>(
+    mut squad_rcon: SquadRcon<T>,
+    job_receiver: Receiver<(Priority, Job<T>)>,
+    subscribers: Subscribers,
+    mut token_bucket: Option<TokenBucket>,
+) {
+    let mut queue = BinaryHeap::new();
+    let mut sequence = 0;
+    let mut connected = true;
+    let mut push = |queue: &mut BinaryHeap<Queued<T>>, (priority, job): (Priority, Job<T>)| {
+        sequence += 1;
+        queue.push(Queued {
+            priority,
+            sequence: Reverse(sequence),
+            job,
+        });
+    };
+
+    loop {
+        // Queue everything sent so far, so the most urgent job runs next
+        while connected {
+            match job_receiver.try_recv() {
+                Ok(job) => push(&mut queue, job),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => connected = false,
+            }
+        }
+        if queue.is_empty() {
+            match job_receiver.recv() {
+                Ok(job) => push(&mut queue, job),
+                Err(_) => return,
+            }
+            continue;
+        }
+
+        if let Some(token_bucket) = token_bucket.as_mut() {
+            let wait_time = token_bucket.wait_time();
+            if wait_time > Duration::ZERO {
+                // Anything queued while waiting may be more urgent
+                match job_receiver.recv_timeout(wait_time) {
+                    Ok(job) => push(&mut queue, job),
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => thread::sleep(wait_time),
+                }
+                continue;
+            }
+        }
+
+        let queued = queue.pop().unwrap();
+        let commands_sent = squad_rcon.commands_sent();
+        (queued.job)(&mut squad_rcon);
+        if let Some(token_bucket) = token_bucket.as_mut() {
+            token_bucket.take(squad_rcon.commands_sent() - commands_sent);
+        }
+        // Only a direct connection collects events while running commands
+        for event in squad_rcon.take_events() {
+            broadcast(&subscribers, event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SquadRconHandle;
+    use crate::mock::fixtures::{exact_config, server};
+    use crate::mock::MockServer;
+    use crate::{Preset, RateLimit, SquadRcon};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn shared_between_threads() {
+        let server = server();
+        let handle =
+            SquadRconHandle::connect_with_config(server.addr(), "password", exact_config())
+                .unwrap();
+
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let handle = handle.clone();
+                thread::spawn(move || handle.maps().unwrap())
+            })
+            .collect();
+        for thread in threads {
+            assert_eq!(thread.join().unwrap().1, "Gorodok_RAAS_v1");
+        }
+        assert_eq!(server.received_commands().len(), 4);
+    }
+
+    #[test]
+    fn broadcast_events() {
+        let server = MockServer::start("password").unwrap();
+        let handle = SquadRconHandle::connect(server.addr(), "password").unwrap();
+        let first = handle.subscribe();
+        let second = handle.clone().subscribe();
+
+        server
+            .inject_chat("[ChatAll] [SteamID:76561198000000001] Alice : hello")
+            .unwrap();
+        for subscriber in [first, second] {
+            let event = subscriber.recv_timeout(Duration::from_secs(5)).unwrap();
+            assert_eq!(event.chat().unwrap().message(), "hello");
+        }
+    }
+
+    #[test]
+    fn broadcast_direct_events() {
+        let server = server();
+        let squad_rcon =
+            SquadRcon::connect_with_config(server.addr(), "password", exact_config()).unwrap();
+        let handle = SquadRconHandle::new(squad_rcon, None);
+        let events = handle.subscribe();
+
+        server
+            .inject_chat("[ChatAll] [SteamID:76561198000000001] Alice : hello")
+            .unwrap();
+        handle.maps().unwrap();
+        let event = events.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(event.chat().unwrap().message(), "hello");
+    }
+
+    #[test]
+    fn survives_panicking_call() {
+        let server = server();
+        let handle =
+            SquadRconHandle::connect_with_config(server.addr(), "password", exact_config())
+                .unwrap();
+
+        let panicking = handle.clone();
+        let result = thread::spawn(move || {
+            panicking.call(|_| -> Result<(), crate::Error> { panic!("job failed") })
+        })
+        .join();
+        assert!(result.is_err());
+        assert_eq!(handle.maps().unwrap().1, "Gorodok_RAAS_v1");
+    }
+
+    #[test]
+    fn actor_exits_with_last_handle() {
+        let server = MockServer::start("password").unwrap();
+        let handle = SquadRconHandle::connect(server.addr(), "password").unwrap();
+        let events = handle.subscribe();
+        drop(handle);
+        assert!(events.recv_timeout(Duration::from_secs(5)).is_err());
+    }
+
+    #[test]
+    fn moderation_jumps_the_queue() {
+        let server = MockServer::start("password").unwrap();
+        let (squad_rcon, event_receiver) =
+            SquadRcon::connect_threaded_with_config(server.addr(), "password", exact_config())
+                .unwrap();
+        let rate_limit = RateLimit::new().with_burst(1).with_rate(5.0);
+        let handle =
+            SquadRconHandle::with_rate_limit(squad_rcon, Some(event_receiver), Some(rate_limit));
+
+        let mut threads = Vec::new();
+        for i in 0..3 {
+            let handle = handle.clone();
+            threads.push(thread::spawn(move || {
+                handle.broadcast(format!("message {}", i)).unwrap();
+            }));
+            thread::sleep(Duration::from_millis(20));
+        }
+        let kick = handle.clone();
+        threads.push(thread::spawn(move || {
+            kick.kick("Bob", "spamming").unwrap();
+        }));
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let commands = server.received_commands();
+        assert_eq!(commands.len(), 4);
+        assert!(commands[0].starts_with("AdminBroadcast"));
+        assert!(commands[1].starts_with("AdminKick"), "{:?}", commands);
+    }
+
+    #[test]
+    fn rate_limit_every_command() {
+        let server = server();
+        let (squad_rcon, event_receiver) =
+            SquadRcon::connect_threaded_with_config(server.addr(), "password", exact_config())
+                .unwrap();
+        let rate_limit = RateLimit::new().with_burst(1).with_rate(5.0);
+        let handle =
+            SquadRconHandle::with_rate_limit(squad_rcon, Some(event_receiver), Some(rate_limit));
+
+        let start = Instant::now();
+        handle
+            .call(|squad_rcon| squad_rcon.apply_preset(&Preset::event()))
+            .unwrap();
+        handle.maps().unwrap();
+        // The preset's three commands leave the bucket two tokens short
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(550), "{:?}", elapsed);
+    }
+}
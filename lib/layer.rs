@@ -0,0 +1,283 @@
+use crate::{Error, LayerInfo};
+use serde::{Deserialize, Serialize};
+
+/// The game mode of a layer
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum GameMode {
+    Raas,
+    Aas,
+    Invasion,
+    Skirmish,
+    TerritoryControl,
+    Seed,
+    Insurgency,
+    Destruction,
+    Training,
+}
+
+impl GameMode {
+    /// Parse a game mode as written in layer names, such as `RAAS` or `TC`,
+    /// ignoring case
+    pub fn from_name(name: &str) -> Option<GameMode> {
+        match name.to_ascii_lowercase().as_str() {
+            "raas" => Some(GameMode::Raas),
+            "aas" => Some(GameMode::Aas),
+            "invasion" => Some(GameMode::Invasion),
+            "skirmish" => Some(GameMode::Skirmish),
+            "tc" | "territorycontrol" => Some(GameMode::TerritoryControl),
+            "seed" => Some(GameMode::Seed),
+            "insurgency" => Some(GameMode::Insurgency),
+            "destruction" => Some(GameMode::Destruction),
+            "training" => Some(GameMode::Training),
+            _ => None,
+        }
+    }
+
+    /// The game mode as written in layer names
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameMode::Raas => "RAAS",
+            GameMode::Aas => "AAS",
+            GameMode::Invasion => "Invasion",
+            GameMode::Skirmish => "Skirmish",
+            GameMode::TerritoryControl => "TC",
+            GameMode::Seed => "Seed",
+            GameMode::Insurgency => "Insurgency",
+            GameMode::Destruction => "Destruction",
+            GameMode::Training => "Training",
+        }
+    }
+}
+
+impl std::fmt::Display for GameMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// A layer name, such as `Narva_RAAS_v1`, broken into its parts.
+///
+/// ```
+/// use squad_rcon::{GameMode, Layer};
+///
+/// let layer = Layer::parse("Al_Basrah_Insurgency_v1").unwrap();
+/// assert_eq!(layer.map(), "Al_Basrah");
+/// assert_eq!(layer.game_mode(), GameMode::Insurgency);
+/// assert_eq!(layer.version(), Some(1));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct Layer {
+    name: String,
+    map: String,
+    game_mode: GameMode,
+    version: Option<u32>,
+    factions: Option<(String, String)>,
+}
+
+impl Layer {
+    /// Parse a layer name. Words may be separated by underscores or spaces,
+    /// and a faction pairing may follow the version, such as
+    /// `Narva_RAAS_v1 USA RGF` or `Narva_RAAS_v1_USA-RGF`.
+    ///
+    /// Fails with `Error::SquadParsingError` if the name has no known game
+    /// mode.
+    pub fn parse(name: &str) -> Result<Layer, Error> {
+        let name = name.trim();
+        let is_separator = |c: char| c == '_' || c == ' ';
+
+        let mut game_mode = None;
+        let mut offset = 0;
+        for word in name.split(is_separator) {
+            if let Some(mode) = GameMode::from_name(word).filter(|_| offset > 0) {
+                game_mode = Some((mode, offset, offset + word.len()));
+                break;
+            }
+            offset += word.len() + 1;
+        }
+        let (game_mode, start, end) = game_mode.ok_or(Error::SquadParsingError)?;
+
+        let mut rest = name[end..]
+            .split(|c| is_separator(c) || c == '-')
+            .filter(|word| !word.is_empty())
+            .peekable();
+        let version = rest
+            .peek()
+            .and_then(|word| word.strip_prefix(['v', 'V']))
+            .and_then(|version| version.parse().ok());
+        if version.is_some() {
+            rest.next();
+        }
+        let rest: Vec<&str> = rest.collect();
+        let factions = match rest.as_slice() {
+            [first, second] => Some((first.to_string(), second.to_string())),
+            _ => None,
+        };
+
+        Ok(Layer {
+            name: name.to_string(),
+            map: name[..start].trim_end_matches(is_separator).to_string(),
+            game_mode,
+            version,
+            factions,
+        })
+    }
+
+    /// The layer name, as sent by the server
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    /// The map the layer is played on, such as `Narva`
+    pub fn map(&self) -> &str {
+        &self.map
+    }
+    pub fn game_mode(&self) -> GameMode {
+        self.game_mode
+    }
+    pub fn version(&self) -> Option<u32> {
+        self.version
+    }
+    /// The two factions playing the layer, if they are known
+    pub fn factions(&self) -> Option<(&str, &str)> {
+        self.factions
+            .as_ref()
+            .map(|(first, second)| (first.as_str(), second.as_str()))
+    }
+}
+
+impl std::convert::TryFrom<&LayerInfo> for Layer {
+    type Error = Error;
+
+    /// Parse the layer name, taking the factions from the server's report if
+    /// the name does not include them
+    fn try_from(layer_info: &LayerInfo) -> Result<Layer, Error> {
+        let mut layer = Layer::parse(layer_info.layer())?;
+        if layer.factions.is_none() {
+            let factions: Vec<&str> = layer_info
+                .factions()
+                .unwrap_or_default()
+                .split_whitespace()
+                .collect();
+            if let [first, second] = factions.as_slice() {
+                layer.factions = Some((first.to_string(), second.to_string()));
+            }
+        }
+        Ok(layer)
+    }
+}
+
+impl std::fmt::Display for Layer {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+/// Selects layers by map, game mode and faction. Criteria which are not set
+/// match every layer.
+///
+/// ```
+/// use squad_rcon::{GameMode, Layer, LayerFilter};
+///
+/// let layers = vec![
+///     Layer::parse("Narva_RAAS_v1").unwrap(),
+///     Layer::parse("Narva_AAS_v1").unwrap(),
+///     Layer::parse("Gorodok_RAAS_v1").unwrap(),
+/// ];
+/// let filter = LayerFilter::new()
+///     .with_map("narva")
+///     .with_game_mode(GameMode::Raas);
+/// assert_eq!(filter.filter(layers).len(), 1);
+/// ```
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct LayerFilter {
+    map: Option<String>,
+    game_modes: Vec<GameMode>,
+    faction: Option<String>,
+}
+
+impl LayerFilter {
+    pub fn new() -> LayerFilter {
+        LayerFilter::default()
+    }
+
+    /// Only match layers on this map, ignoring case
+    pub fn with_map<S: Into<String>>(mut self, map: S) -> LayerFilter {
+        self.map = Some(map.into());
+        self
+    }
+
+    /// Only match layers of this game mode. Calling this again also matches
+    /// the other game modes given.
+    pub fn with_game_mode(mut self, game_mode: GameMode) -> LayerFilter {
+        self.game_modes.push(game_mode);
+        self
+    }
+
+    /// Only match layers where this faction plays, ignoring case
+    pub fn with_faction<S: Into<String>>(mut self, faction: S) -> LayerFilter {
+        self.faction = Some(faction.into());
+        self
+    }
+
+    pub fn matches(&self, layer: &Layer) -> bool {
+        let map = self
+            .map
+            .as_ref()
+            .map_or(true, |map| layer.map().eq_ignore_ascii_case(map));
+        let game_mode = self.game_modes.is_empty() || self.game_modes.contains(&layer.game_mode());
+        let faction = self.faction.as_ref().map_or(true, |faction| {
+            layer.factions().is_some_and(|(first, second)| {
+                first.eq_ignore_ascii_case(faction) || second.eq_ignore_ascii_case(faction)
+            })
+        });
+        map && game_mode && faction
+    }
+
+    /// The layers which match this filter
+    pub fn filter<I: IntoIterator<Item = Layer>>(&self, layers: I) -> Vec<Layer> {
+        layers
+            .into_iter()
+            .filter(|layer| self.matches(layer))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GameMode, Layer, LayerFilter};
+    use crate::LayerInfo;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn parse() {
+        let layer = Layer::parse("Narva_RAAS_v1").unwrap();
+        assert_eq!(layer.map(), "Narva");
+        assert_eq!(layer.game_mode(), GameMode::Raas);
+        assert_eq!(layer.version(), Some(1));
+        assert_eq!(layer.factions(), None);
+
+        let layer = Layer::parse("Kohat TC v2 USA-RGF").unwrap();
+        assert_eq!(layer.map(), "Kohat");
+        assert_eq!(layer.game_mode(), GameMode::TerritoryControl);
+        assert_eq!(layer.factions(), Some(("USA", "RGF")));
+
+        let layer = Layer::parse("Jensens_Training").unwrap();
+        assert_eq!(layer.game_mode(), GameMode::Training);
+        assert_eq!(layer.version(), None);
+
+        assert!(Layer::parse("Narva").is_err());
+        assert!(Layer::parse("RAAS_v1").is_err());
+    }
+
+    #[test]
+    fn from_layer_info() {
+        let layer_info = LayerInfo::new(
+            Some("Narva".to_string()),
+            "Narva_RAAS_v1".to_string(),
+            Some("USA RGF".to_string()),
+        );
+        let layer = Layer::try_from(&layer_info).unwrap();
+        assert_eq!(layer.factions(), Some(("USA", "RGF")));
+        assert!(LayerFilter::new().with_faction("rgf").matches(&layer));
+        assert!(!LayerFilter::new().with_faction("MEA").matches(&layer));
+    }
+}
@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+
+/// A layer which is running, or set to run next, as reported by
+/// `ShowCurrentMap` and `ShowNextMap`.
+///
+/// Older servers only report the layer name, so `level` and `factions` are
+/// `None` for them.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LayerInfo {
+    level: Option<String>,
+    layer: String,
+    factions: Option<String>,
+}
+
+impl LayerInfo {
+    pub fn new(level: Option<String>, layer: String, factions: Option<String>) -> LayerInfo {
+        LayerInfo {
+            level,
+            layer,
+            factions,
+        }
+    }
+
+    /// The level, or map, the layer is played on, such as `Narva`
+    pub fn level(&self) -> Option<&str> {
+        self.level.as_deref()
+    }
+    /// The layer name, such as `Narva_RAAS_v1`
+    pub fn layer(&self) -> &str {
+        &self.layer
+    }
+    /// The factions playing the layer, as reported by the server
+    pub fn factions(&self) -> Option<&str> {
+        self.factions.as_deref()
+    }
+}
+
+impl std::fmt::Display for LayerInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.factions {
+            Some(factions) => write!(f, "{} ({})", self.layer, factions),
+            None => write!(f, "{}", self.layer),
+        }
+    }
+}
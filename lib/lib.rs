@@ -1,48 +1,162 @@
+mod admin;
+#[cfg(feature = "async")]
+mod async_rcon;
+#[cfg(feature = "async")]
+mod async_squad_rcon;
 mod chat;
+mod command;
+mod commands;
+mod config;
+mod disconnected_player;
+mod event;
+mod handle;
+mod layer;
+mod layer_info;
+mod match_rules;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+mod parse;
 mod player;
+mod player_list;
+mod rate_limit;
 mod rcon;
+mod reconnect;
+mod snapshot;
 mod squad;
 mod squad_rcon;
 mod team;
+mod terminator;
+mod threaded_rcon;
+mod transport;
 
+#[cfg(feature = "async")]
+pub use crate::async_rcon::AsyncRconClient;
+#[cfg(feature = "async")]
+pub use crate::async_squad_rcon::AsyncSquadRcon;
+pub use crate::rcon::{RconClient, RconPacket};
 pub use crate::squad_rcon::{SquadRcon, SERVERDATA_CHAT};
+pub use admin::AdminActionResult;
 pub use chat::Chat;
+pub use command::Command;
+pub use config::RconConfig;
+pub use disconnected_player::DisconnectedPlayer;
+pub use event::ServerEvent;
+pub use handle::SquadRconHandle;
+pub use layer::{GameMode, Layer, LayerFilter};
+pub use layer_info::LayerInfo;
+pub use match_rules::{MatchRule, Preset};
 pub use player::Player;
+pub use player_list::PlayerList;
+pub use rate_limit::{Priority, RateLimit};
+pub use reconnect::ReconnectPolicy;
+pub use snapshot::Snapshot;
 pub use squad::Squad;
 pub use team::Team;
+pub use terminator::Terminator;
+pub use transport::{MemoryListener, MemoryTransport, TcpTransport, Transport};
 
 #[derive(Debug)]
 pub enum Error {
+    /// A player name matched more than one player. Holds the server's
+    /// response.
+    AmbiguousName(String),
     AuthenticationFailure,
+    /// The server refused a command. Holds the server's response.
+    CommandFailed(String),
+    /// A command was not answered within its timeout. Unlike `Timeout`, the
+    /// connection is still usable.
+    CommandTimeout,
+    /// No address could be connected to. Holds the error for each address
+    /// which was tried.
+    ConnectFailed(Vec<(std::net::SocketAddr, Error)>),
     Disconnected,
     EmptyPacketBody,
     FromUtf8Error(std::string::FromUtf8Error),
+    InvalidPacketSize(i32),
+    /// A command argument contains a control character, is out of range,
+    /// such as a player limit or slomo scale, or a command was otherwise
+    /// malformed. Holds the offending argument.
+    InvalidArgument(String),
+    /// The server did not recognise a map or layer. Holds the server's
+    /// response.
+    InvalidLayer(String),
     IoError(std::io::Error),
+    /// The host did not resolve to any address. Holds the host, if it is
+    /// known.
+    NoAddresses(String),
+    PacketTooLarge(usize),
     ParseIntError(std::num::ParseIntError),
+    /// No player matched a name or steam id. Holds the server's response.
+    PlayerNotFound(String),
     ProtocolError,
     SquadParsingError,
+    Timeout,
 }
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
+            Error::AmbiguousName(response) => write!(f, "Ambiguous player name: {}", response),
             Error::AuthenticationFailure => write!(f, "Authentication Failure"),
+            Error::CommandFailed(response) => write!(f, "Command failed: {}", response),
+            Error::CommandTimeout => write!(f, "Timed out waiting for a command's response"),
+            Error::ConnectFailed(errors) => {
+                write!(f, "Could not connect to any address")?;
+                for (i, (addr, error)) in errors.iter().enumerate() {
+                    let separator = if i == 0 { ": " } else { ", " };
+                    write!(f, "{}{} ({})", separator, addr, error)?;
+                }
+                Ok(())
+            }
             Error::Disconnected => write!(f, "Disconnected"),
             Error::EmptyPacketBody => write!(f, "Empty packet body"),
             Error::FromUtf8Error(from_utf8_error) => write!(f, "{}", from_utf8_error),
+            Error::InvalidPacketSize(size) => write!(f, "Invalid packet size {}", size),
+            Error::InvalidArgument(argument) => {
+                write!(f, "Invalid command argument {:?}", argument)
+            }
+            Error::InvalidLayer(response) => write!(f, "Invalid layer: {}", response),
             Error::IoError(io_error) => write!(f, "{}", io_error),
+            Error::NoAddresses(host) if host.is_empty() => write!(f, "No addresses to connect to"),
+            Error::NoAddresses(host) => write!(f, "{} did not resolve to any address", host),
+            Error::PacketTooLarge(length) => {
+                write!(f, "Packet body of {} bytes is too large", length)
+            }
             Error::ParseIntError(parse_int_error) => write!(f, "{}", parse_int_error),
+            Error::PlayerNotFound(response) => write!(f, "Player not found: {}", response),
             Error::ProtocolError => write!(f, "Protocol Error"),
             Error::SquadParsingError => write!(f, "Squad Parsing Error"),
+            Error::Timeout => write!(f, "Timed out waiting for the server"),
         }
     }
 }
 
+impl Error {
+    /// Returns true if this error means the connection to the server was lost
+    pub fn is_connection_error(&self) -> bool {
+        // After a malformed packet, the stream can no longer be framed
+        matches!(
+            self,
+            Error::ConnectFailed(_)
+                | Error::Disconnected
+                | Error::InvalidPacketSize(_)
+                | Error::IoError(_)
+                | Error::NoAddresses(_)
+                | Error::PacketTooLarge(_)
+                | Error::Timeout
+        )
+    }
+}
+
 impl std::error::Error for Error {}
 
 impl From<std::io::Error> for Error {
     fn from(io_error: std::io::Error) -> Error {
-        Error::IoError(io_error)
+        match io_error.kind() {
+            // Socket timeouts are reported as either, depending on the platform
+            std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => Error::Timeout,
+            _ => Error::IoError(io_error),
+        }
     }
 }
 
@@ -0,0 +1,204 @@
+use crate::{commands, Error};
+use serde::{Deserialize, Serialize};
+
+/// A server rule which can be switched on or off during a match, such as for
+/// training or event nights. Every rule is off by default.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum MatchRule {
+    /// Every role is available, regardless of squad size or team limits
+    ForceAllRoleAvailability,
+    /// Every vehicle is available, regardless of team limits
+    ForceAllVehicleAvailability,
+    /// Every deployable can be built, regardless of team limits
+    ForceAllDeployableAvailability,
+    /// Players respawn without waiting
+    NoRespawnTimer,
+    /// Players can change teams without waiting
+    NoTeamChangeTimer,
+    /// Vehicles can be used without being claimed by a squad
+    DisableVehicleClaiming,
+}
+
+impl MatchRule {
+    pub const ALL: [MatchRule; 6] = [
+        MatchRule::ForceAllRoleAvailability,
+        MatchRule::ForceAllVehicleAvailability,
+        MatchRule::ForceAllDeployableAvailability,
+        MatchRule::NoRespawnTimer,
+        MatchRule::NoTeamChangeTimer,
+        MatchRule::DisableVehicleClaiming,
+    ];
+
+    /// The name of the admin command which sets this rule
+    pub fn command_name(&self) -> &'static str {
+        match self {
+            MatchRule::ForceAllRoleAvailability => "AdminForceAllRoleAvailability",
+            MatchRule::ForceAllVehicleAvailability => "AdminForceAllVehicleAvailability",
+            MatchRule::ForceAllDeployableAvailability => "AdminForceAllDeployableAvailability",
+            MatchRule::NoRespawnTimer => "AdminNoRespawnTimer",
+            MatchRule::NoTeamChangeTimer => "AdminNoTeamChangeTimer",
+            MatchRule::DisableVehicleClaiming => "AdminDisableVehicleClaiming",
+        }
+    }
+
+    /// A short name for this rule, as used by the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            MatchRule::ForceAllRoleAvailability => "all_roles",
+            MatchRule::ForceAllVehicleAvailability => "all_vehicles",
+            MatchRule::ForceAllDeployableAvailability => "all_deployables",
+            MatchRule::NoRespawnTimer => "no_respawn_timer",
+            MatchRule::NoTeamChangeTimer => "no_team_change_timer",
+            MatchRule::DisableVehicleClaiming => "no_vehicle_claiming",
+        }
+    }
+
+    /// Find a rule by its short name
+    pub fn from_name(name: &str) -> Option<MatchRule> {
+        MatchRule::ALL
+            .iter()
+            .copied()
+            .find(|rule| rule.name() == name)
+    }
+}
+
+/// A named set of match rules, which can be applied or reverted at once with
+/// `SquadRcon::apply_preset` and `SquadRcon::revert_preset`.
+///
+/// ```
+/// use squad_rcon::{MatchRule, Preset};
+///
+/// let preset = Preset::new("seeding")
+///     .with_rule(MatchRule::NoRespawnTimer, true)
+///     .with_rule(MatchRule::NoTeamChangeTimer, true);
+/// assert_eq!(preset.rules().len(), 2);
+/// ```
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Preset {
+    name: String,
+    rules: Vec<(MatchRule, bool)>,
+}
+
+impl Preset {
+    pub fn new<S: Into<String>>(name: S) -> Preset {
+        Preset {
+            name: name.into(),
+            rules: Vec::new(),
+        }
+    }
+
+    /// Every rule switched on, for training against an empty server
+    pub fn training() -> Preset {
+        MatchRule::ALL
+            .iter()
+            .fold(Preset::new("training"), |preset, rule| {
+                preset.with_rule(*rule, true)
+            })
+    }
+
+    /// Every role, vehicle and deployable available, with normal timers, for
+    /// event nights
+    pub fn event() -> Preset {
+        Preset::new("event")
+            .with_rule(MatchRule::ForceAllRoleAvailability, true)
+            .with_rule(MatchRule::ForceAllVehicleAvailability, true)
+            .with_rule(MatchRule::ForceAllDeployableAvailability, true)
+    }
+
+    /// The built in presets
+    pub fn presets() -> Vec<Preset> {
+        vec![Preset::training(), Preset::event()]
+    }
+
+    /// Find a built in preset by name
+    pub fn from_name(name: &str) -> Option<Preset> {
+        Preset::presets()
+            .into_iter()
+            .find(|preset| preset.name == name)
+    }
+
+    /// Set a rule when the preset is applied, replacing any earlier setting
+    /// of the same rule
+    pub fn with_rule(mut self, rule: MatchRule, enabled: bool) -> Preset {
+        self.rules.retain(|(r, _)| *r != rule);
+        self.rules.push((rule, enabled));
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn rules(&self) -> &[(MatchRule, bool)] {
+        &self.rules
+    }
+
+    /// This preset with every one of its rules set back to the default, off
+    pub fn reverted(&self) -> Preset {
+        Preset {
+            name: self.name.clone(),
+            rules: self.rules.iter().map(|(rule, _)| (*rule, false)).collect(),
+        }
+    }
+
+    /// The commands which apply this preset
+    pub(crate) fn commands(&self) -> Result<Vec<String>, Error> {
+        self.rules
+            .iter()
+            .map(|(rule, enabled)| commands::match_rule(*rule, *enabled))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MatchRule, Preset};
+    use crate::mock::fixtures::{exact_config, server};
+    use crate::SquadRcon;
+
+    #[test]
+    fn names() {
+        for rule in MatchRule::ALL.iter() {
+            assert_eq!(MatchRule::from_name(rule.name()), Some(*rule));
+        }
+        assert_eq!(Preset::from_name("training").unwrap().rules().len(), 6);
+        assert!(Preset::from_name("nope").is_none());
+    }
+
+    #[test]
+    fn revert() {
+        let preset = Preset::event().with_rule(MatchRule::NoRespawnTimer, true);
+        assert_eq!(
+            preset.reverted().commands().unwrap(),
+            vec![
+                "AdminForceAllRoleAvailability 0",
+                "AdminForceAllVehicleAvailability 0",
+                "AdminForceAllDeployableAvailability 0",
+                "AdminNoRespawnTimer 0",
+            ]
+        );
+    }
+
+    #[test]
+    fn match_rules() {
+        let server = server();
+        let mut squad_rcon =
+            SquadRcon::connect_with_config(server.addr(), "password", exact_config()).unwrap();
+
+        squad_rcon.no_respawn_timer(true).unwrap();
+        let preset = Preset::new("scrim")
+            .with_rule(MatchRule::DisableVehicleClaiming, true)
+            .with_rule(MatchRule::NoTeamChangeTimer, true);
+        assert_eq!(squad_rcon.apply_preset(&preset).unwrap().len(), 2);
+        squad_rcon.revert_preset(&preset).unwrap();
+        assert_eq!(
+            server.received_commands(),
+            vec![
+                "AdminNoRespawnTimer 1",
+                "AdminDisableVehicleClaiming 1",
+                "AdminNoTeamChangeTimer 1",
+                "AdminDisableVehicleClaiming 0",
+                "AdminNoTeamChangeTimer 0"
+            ]
+        );
+    }
+}
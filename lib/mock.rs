@@ -0,0 +1,397 @@
+//! A local mock squad rcon server, for testing.
+//!
+//! `MockServer` listens on localhost, performs the SERVERDATA_AUTH handshake
+//! the same way a squad server does, and answers commands from fixtures which
+//! can be changed while clients are connected. Chat, or any other unsolicited
+//! packet, can be pushed to every connected client on demand.
+//!
+//! ```no_run
+//! use squad_rcon::mock::MockServer;
+//! use squad_rcon::SquadRcon;
+//!
+//! let server = MockServer::start("password").unwrap();
+//! server.set_next_map("Current map is Narva_RAAS_v1, Next map is Gorodok_RAAS_v1");
+//!
+//! let mut squad_rcon = SquadRcon::connect(server.addr(), "password").unwrap();
+//! assert_eq!(squad_rcon.maps().unwrap().0, "Narva_RAAS_v1");
+//! ```
+//!
+//! The same server can also be reached without a socket, through
+//! `MockServer::connect_in_memory`.
+
+use crate::rcon::{
+    RconPacket, DEFAULT_MAX_BODY_LENGTH, SERVERDATA_AUTH, SERVERDATA_AUTH_RESPONSE,
+    SERVERDATA_EXECCOMMAND, SERVERDATA_RESPONSE_VALUE,
+};
+use crate::transport::{MemoryTransport, TcpTransport, Transport};
+use crate::{Error, SERVERDATA_CHAT};
+use log::debug;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// The write half of a connected client, of any transport
+trait Client: Write + Send {
+    fn close(&self);
+}
+
+impl<T: Transport> Client for T {
+    fn close(&self) {
+        self.shutdown().ok();
+    }
+}
+
+#[derive(Default)]
+struct State {
+    responses: HashMap<String, String>,
+    received_commands: Vec<String>,
+    ignored_commands: HashSet<String>,
+    silent: bool,
+    ignore_empty_response: bool,
+    fragment_size: Option<usize>,
+    response_delay: Option<Duration>,
+    clients: Vec<Arc<Mutex<dyn Client>>>,
+}
+
+/// A mock squad rcon server listening on localhost.
+///
+/// The server stops listening when dropped.
+pub struct MockServer {
+    addr: SocketAddr,
+    memory_listener: Sender<MemoryTransport>,
+    password: Arc<String>,
+    state: Arc<Mutex<State>>,
+    shutdown: Arc<AtomicBool>,
+}
+
+impl MockServer {
+    /// Start a mock server on an ephemeral localhost port, accepting the
+    /// given rcon password.
+    pub fn start<S: Into<String>>(password: S) -> Result<MockServer, Error> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let (memory_listener, memory_receiver) = channel();
+
+        let mock_server = MockServer {
+            addr: listener.local_addr()?,
+            memory_listener,
+            password: Arc::new(password.into()),
+            state: Arc::new(Mutex::new(State::default())),
+            shutdown: Arc::new(AtomicBool::new(false)),
+        };
+
+        let password = mock_server.password.clone();
+        let state = mock_server.state.clone();
+        let shutdown = mock_server.shutdown.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if shutdown.load(Ordering::SeqCst) {
+                    break;
+                }
+                let transport = match stream
+                    .map_err(Error::from)
+                    .and_then(TcpTransport::from_stream)
+                {
+                    Ok(transport) => transport,
+                    Err(_) => continue,
+                };
+                spawn_serve(transport, password.clone(), state.clone());
+            }
+        });
+
+        let password = mock_server.password.clone();
+        let state = mock_server.state.clone();
+        let shutdown = mock_server.shutdown.clone();
+        thread::spawn(move || {
+            for transport in memory_receiver {
+                if shutdown.load(Ordering::SeqCst) {
+                    break;
+                }
+                spawn_serve(transport, password.clone(), state.clone());
+            }
+        });
+
+        Ok(mock_server)
+    }
+
+    /// Connect to this server in memory, without a socket.
+    ///
+    /// The returned transport reconnects to this same server.
+    pub fn connect_in_memory(&self) -> MemoryTransport {
+        MemoryTransport::connect(&self.memory_listener)
+    }
+
+    /// The address this server is listening on
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Set the response the server gives to `command`.
+    ///
+    /// Commands without a fixture are answered with an empty response.
+    pub fn set_response<C: Into<String>, R: Into<String>>(&self, command: C, response: R) {
+        self.state
+            .lock()
+            .unwrap()
+            .responses
+            .insert(command.into(), response.into());
+    }
+
+    /// Set the response to `ListPlayers`
+    pub fn set_players<R: Into<String>>(&self, response: R) {
+        self.set_response("ListPlayers", response);
+    }
+
+    /// Set the response to `ListSquads`
+    pub fn set_squads<R: Into<String>>(&self, response: R) {
+        self.set_response("ListSquads", response);
+    }
+
+    /// Set the response to `ShowNextMap`
+    pub fn set_next_map<R: Into<String>>(&self, response: R) {
+        self.set_response("ShowNextMap", response);
+    }
+
+    /// Set the response to `ShowCurrentMap`
+    pub fn set_current_map<R: Into<String>>(&self, response: R) {
+        self.set_response("ShowCurrentMap", response);
+    }
+
+    /// Set the response to `ListMaps`
+    pub fn set_maps<R: Into<String>>(&self, response: R) {
+        self.set_response("ListMaps", response);
+    }
+
+    /// Stop answering commands, while keeping connections open, to simulate
+    /// a server which has gone away without closing the connection.
+    pub fn set_silent(&self, silent: bool) {
+        self.state.lock().unwrap().silent = silent;
+    }
+
+    /// Never answer `command`, as older servers do with `ShowCurrentMap`
+    pub fn ignore_command<C: Into<String>>(&self, command: C) {
+        self.state
+            .lock()
+            .unwrap()
+            .ignored_commands
+            .insert(command.into());
+    }
+
+    /// Ignore empty SERVERDATA_RESPONSE_VALUE packets, instead of mirroring
+    /// them back, to simulate a server which does not support
+    /// `Terminator::EmptyResponse`.
+    pub fn set_ignore_empty_response(&self, ignore_empty_response: bool) {
+        self.state.lock().unwrap().ignore_empty_response = ignore_empty_response;
+    }
+
+    /// Split responses into packets with bodies of at most `fragment_size`
+    /// bytes, the way a squad server splits long responses, or `None` to
+    /// always answer with a single packet.
+    pub fn set_fragment_size(&self, fragment_size: Option<usize>) {
+        self.state.lock().unwrap().fragment_size = fragment_size;
+    }
+
+    /// Wait before answering each command, to simulate a slow server. Later
+    /// commands from the same client wait behind it.
+    pub fn set_response_delay(&self, response_delay: Option<Duration>) {
+        self.state.lock().unwrap().response_delay = response_delay;
+    }
+
+    /// Every command the server has received, in order, across all clients.
+    pub fn received_commands(&self) -> Vec<String> {
+        self.state.lock().unwrap().received_commands.clone()
+    }
+
+    /// Send a packet of the given type to every connected client.
+    pub fn inject<S: Into<String>>(&self, type_: i32, body: S) -> Result<(), Error> {
+        let data = RconPacket::new(0, type_, body).encode()?;
+        let clients = self.state.lock().unwrap().clients.clone();
+        for client in clients {
+            client.lock().unwrap().write_all(&data)?;
+        }
+        Ok(())
+    }
+
+    /// Send a SERVERDATA_CHAT packet to every connected client.
+    ///
+    /// For a chat message, `body` looks like
+    /// `[ChatAll] [SteamID:76561198000000000] player : message`.
+    pub fn inject_chat<S: Into<String>>(&self, body: S) -> Result<(), Error> {
+        self.inject(SERVERDATA_CHAT, body)
+    }
+
+    /// Disconnect every connected client.
+    pub fn disconnect_all(&self) {
+        let clients = std::mem::take(&mut self.state.lock().unwrap().clients);
+        for client in clients {
+            client.lock().unwrap().close();
+        }
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        self.disconnect_all();
+        // Wake the accept loop so it sees the shutdown flag
+        TcpStream::connect(self.addr).ok();
+    }
+}
+
+fn spawn_serve<T: Transport>(transport: T, password: Arc<String>, state: Arc<Mutex<State>>) {
+    thread::spawn(move || {
+        if let Err(e) = serve(transport, &password, &state) {
+            debug!("mock server connection closed: {}", e);
+        }
+    });
+}
+
+fn serve<T: Transport>(mut stream: T, password: &str, state: &Mutex<State>) -> Result<(), Error> {
+    let writer = Arc::new(Mutex::new(stream.try_clone()?));
+    state.lock().unwrap().clients.push(writer.clone());
+
+    let send = |packet: RconPacket| -> Result<(), Error> {
+        writer.lock().unwrap().write_all(&packet.encode()?)?;
+        Ok(())
+    };
+
+    loop {
+        let packet = RconPacket::decode(&mut stream, DEFAULT_MAX_BODY_LENGTH)?;
+        match packet.type_() {
+            SERVERDATA_AUTH => {
+                send(RconPacket::new(packet.id(), SERVERDATA_RESPONSE_VALUE, ""))?;
+                let id = if packet.body() == password {
+                    packet.id()
+                } else {
+                    -1
+                };
+                send(RconPacket::new(id, SERVERDATA_AUTH_RESPONSE, ""))?;
+            }
+            SERVERDATA_EXECCOMMAND => {
+                let (response, fragment_size, response_delay) = {
+                    let mut state = state.lock().unwrap();
+                    state.received_commands.push(packet.body().to_string());
+                    if state.silent || state.ignored_commands.contains(packet.body()) {
+                        continue;
+                    }
+                    let response = state
+                        .responses
+                        .get(packet.body())
+                        .cloned()
+                        .unwrap_or_default();
+                    (response, state.fragment_size, state.response_delay)
+                };
+                if let Some(response_delay) = response_delay {
+                    thread::sleep(response_delay);
+                }
+                for fragment in fragments(&response, fragment_size) {
+                    send(RconPacket::new(
+                        packet.id(),
+                        SERVERDATA_RESPONSE_VALUE,
+                        fragment,
+                    ))?;
+                }
+            }
+            SERVERDATA_RESPONSE_VALUE => {
+                {
+                    let state = state.lock().unwrap();
+                    if state.silent || state.ignore_empty_response {
+                        continue;
+                    }
+                }
+                // Like source servers, mirror the packet, then follow it with
+                // a packet whose body is 0x00000100
+                send(RconPacket::new(packet.id(), SERVERDATA_RESPONSE_VALUE, ""))?;
+                send(RconPacket::new(
+                    packet.id(),
+                    SERVERDATA_RESPONSE_VALUE,
+                    "\0\0\u{1}\0",
+                ))?;
+            }
+            _ => return Err(Error::ProtocolError),
+        }
+    }
+}
+
+/// Split `response` into pieces of at most `fragment_size` bytes, without
+/// splitting any character. An empty response is still one piece.
+fn fragments(response: &str, fragment_size: Option<usize>) -> Vec<&str> {
+    let fragment_size = match fragment_size {
+        Some(fragment_size) => fragment_size.max(4),
+        None => return vec![response],
+    };
+    let mut fragments = Vec::new();
+    let mut rest = response;
+    loop {
+        let mut end = rest.len().min(fragment_size);
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let (fragment, remainder) = rest.split_at(end);
+        fragments.push(fragment);
+        if remainder.is_empty() {
+            return fragments;
+        }
+        rest = remainder;
+    }
+}
+
+/// Fixtures shared by the tests of every module
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use super::MockServer;
+    use crate::{RconConfig, Terminator};
+
+    pub(crate) const PLAYERS: &str = "----- Active Players -----
+ID: 0 | SteamID: 76561198000000001 | Name: Alice | Team ID: 1 | Squad ID: 1
+ID: 1 | SteamID: 76561198000000002 | Name: Bob | Team ID: 2 | Squad ID: N/A
+----- Recently Disconnected Players [Max of 15] -----";
+
+    /// `PLAYERS`, with a player who recently disconnected
+    pub(crate) const PLAYERS_WITH_DISCONNECTED: &str = "----- Active Players -----
+ID: 0 | SteamID: 76561198000000001 | Name: Alice | Team ID: 1 | Squad ID: 1
+ID: 1 | SteamID: 76561198000000002 | Name: Bob | Team ID: 2 | Squad ID: N/A
+----- Recently Disconnected Players [Max of 15] -----
+ID: 2 | SteamID: 76561198000000003 | Since Disconnect: 00m.12s | Name: Carol";
+
+    pub(crate) const SQUADS: &str = "----- Active Squads -----
+Team ID: 1 (United States Army)
+ID: 1 | Name: INF | Size: 2 | Locked: False
+Team ID: 2 (Russian Ground Forces)
+ID: 1 | Name: ARMOR | Size: 1 | Locked: True";
+
+    /// A mock server with the password `password`, answering `ListPlayers`,
+    /// `ListSquads`, `ShowNextMap` and `ListMaps` as an older server would
+    pub(crate) fn server() -> MockServer {
+        let server = MockServer::start("password").unwrap();
+        server.set_players(PLAYERS);
+        server.set_squads(SQUADS);
+        server.set_next_map("Current map is Narva_RAAS_v1, Next map is Gorodok_RAAS_v1");
+        server.set_maps("Narva_RAAS_v1\nGorodok_RAAS_v1");
+        server
+    }
+
+    /// Settings which add no commands of their own, so tests can check
+    /// exactly what was sent
+    pub(crate) fn exact_config() -> RconConfig {
+        RconConfig::new().with_terminator(Terminator::EmptyResponse)
+    }
+
+    /// A `ListPlayers` response long enough to be split over many packets
+    pub(crate) fn many_players(count: usize) -> String {
+        let mut players = "----- Active Players -----\n".to_string();
+        for id in 0..count {
+            players += &format!(
+                "ID: {} | SteamID: {} | Name: Player {} | Team ID: 1 | Squad ID: N/A\n",
+                id,
+                76561198000000000u64 + id as u64,
+                id
+            );
+        }
+        players + "----- Recently Disconnected Players [Max of 15] -----"
+    }
+}
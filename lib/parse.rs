@@ -0,0 +1,596 @@
+//! Parsers for the text responses returned by the squad server.
+//!
+//! These are shared between `SquadRcon` and `AsyncSquadRcon`.
+
+use crate::{Chat, DisconnectedPlayer, Error, Layer, LayerInfo, Player, PlayerList, Squad, Team};
+use lazy_static::lazy_static;
+use log::warn;
+use regex::Regex;
+use std::convert::TryFrom;
+use std::time::Duration;
+
+lazy_static! {
+    static ref FIELD_REGEX: Regex =
+        Regex::new(r"^([A-Za-z][A-Za-z ]*?): ?(.*)$").expect("FIELD_REGEX");
+    static ref ONLINE_ID_REGEX: Regex =
+        Regex::new(r"(?i)\b(EOS|steam): *(\S+)").expect("ONLINE_ID_REGEX");
+    static ref TEAM_REGEX: Regex = Regex::new(r"Team ID: (\d*) \((.*)\)").expect("TEAM_REGEX");
+    static ref MAPS_REGEX: Regex =
+        Regex::new(r"Current map is (.*), Next map is (.*)").expect("MAPS_REGEX");
+    static ref LAYER_REGEX: Regex =
+        Regex::new(r"(?:Current|Next) level is (.*?), layer is\s*(.*?)(?:, factions (.*))?$")
+            .expect("LAYER_REGEX");
+    static ref CHAT_REGEX: Regex =
+        Regex::new(r"\[(.*?)\] \[SteamID:(\d*)\] (.*?) : (.*)").expect("CHAT_REGEX");
+}
+
+/// Parse the body of a SERVERDATA_CHAT packet
+pub fn chat(line: &str) -> Result<Chat, Error> {
+    let captures = CHAT_REGEX.captures(line).ok_or(Error::SquadParsingError)?;
+
+    Ok(Chat::new(
+        captures.get(1).unwrap().as_str().to_string(),
+        captures.get(2).unwrap().as_str().to_string(),
+        captures.get(3).unwrap().as_str().to_string(),
+        captures.get(4).unwrap().as_str().to_string(),
+    ))
+}
+
+/// Parse the response to `ListPlayers`, ignoring the recently disconnected
+/// players
+pub fn players(players_string: &str) -> Result<Vec<Player>, Error> {
+    let mut lines = players_string.split('\n').skip(1);
+    section(active_lines(&mut lines), player, "player")
+}
+
+/// How the ids of a player are listed. This has changed as the server has
+/// been updated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum IdFormat {
+    /// `SteamID: 7656...`, and sometimes `EOS ID: 0002...`
+    SteamId,
+    /// `Online IDs: EOS: 0002... steam: 7656...`
+    OnlineIds,
+}
+
+/// Fields whose values are chosen by players, and so may contain " | "
+const FREE_TEXT_KEYS: [&str; 2] = ["Name", "Creator Name"];
+
+/// Every key read by the parsers. Within a free text value, only these start
+/// a new field.
+const KNOWN_KEYS: [&str; 17] = [
+    "ID",
+    "SteamID",
+    "Steam ID",
+    "EOS ID",
+    "Online IDs",
+    "Name",
+    "Team ID",
+    "Squad ID",
+    "Is Leader",
+    "Role",
+    "Since Disconnect",
+    "Size",
+    "Locked",
+    "Creator Name",
+    "Creator Steam ID",
+    "Creator EOS ID",
+    "Creator Online IDs",
+];
+
+/// The `Key: value` fields of one line of `ListPlayers` or `ListSquads`.
+///
+/// Fields which are not known are kept, but ignored by the parsers, so newer
+/// servers which add fields can still be read.
+struct Fields(Vec<(String, String)>);
+
+impl Fields {
+    fn parse(line: &str) -> Fields {
+        let is_key = |key: &str, keys: &[&str]| keys.iter().any(|k| k.eq_ignore_ascii_case(key));
+        let mut fields: Vec<(String, String)> = Vec::new();
+        for segment in line.trim().split(" | ") {
+            let captures = FIELD_REGEX.captures(segment).filter(|captures| {
+                // A name such as "A | Key: B" must not end early
+                is_key(&captures[1], &KNOWN_KEYS)
+                    || !fields
+                        .last()
+                        .is_some_and(|(key, _)| is_key(key, &FREE_TEXT_KEYS))
+            });
+            match (captures, fields.last_mut()) {
+                (Some(captures), _) => {
+                    fields.push((captures[1].to_string(), captures[2].trim().to_string()))
+                }
+                // A name which contains " | "
+                (None, Some((_, value))) => {
+                    value.push_str(" | ");
+                    value.push_str(segment);
+                }
+                (None, None) => {}
+            }
+        }
+        Fields(fields)
+    }
+
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn id(&self) -> Option<usize> {
+        self.get("ID")?.parse().ok()
+    }
+
+    fn optional_id(&self, key: &str) -> Option<usize> {
+        self.get(key)?.parse().ok()
+    }
+
+    fn bool(&self, key: &str) -> Option<bool> {
+        match self.get(key)? {
+            "True" => Some(true),
+            "False" => Some(false),
+            _ => None,
+        }
+    }
+
+    fn id_format(&self, prefix: &str) -> IdFormat {
+        if self.get(&format!("{}Online IDs", prefix)).is_some() {
+            IdFormat::OnlineIds
+        } else {
+            IdFormat::SteamId
+        }
+    }
+
+    /// The `(steam_id, eos_id)` of a player, where `prefix` is `"Creator "`
+    /// for the creator of a squad
+    fn online_ids(&self, prefix: &str) -> (Option<String>, Option<String>) {
+        let non_empty = |id: &str| Some(id.to_string()).filter(|id| !id.is_empty());
+        match self.id_format(prefix) {
+            IdFormat::SteamId => {
+                let steam_id = self
+                    .get(&format!("{}SteamID", prefix))
+                    .or_else(|| self.get(&format!("{}Steam ID", prefix)));
+                let eos_id = self.get(&format!("{}EOS ID", prefix));
+                (steam_id.and_then(non_empty), eos_id.and_then(non_empty))
+            }
+            IdFormat::OnlineIds => {
+                let online_ids = self.get(&format!("{}Online IDs", prefix)).unwrap_or("");
+                let mut ids = (None, None);
+                for captures in ONLINE_ID_REGEX.captures_iter(online_ids) {
+                    if captures[1].eq_ignore_ascii_case("steam") {
+                        ids.0 = non_empty(&captures[2]);
+                    } else {
+                        ids.1 = non_empty(&captures[2]);
+                    }
+                }
+                ids
+            }
+        }
+    }
+}
+
+/// Parse the response to `ListPlayers`.
+///
+/// Lines which cannot be read are skipped, and logged. If no line of a
+/// section can be read, this fails with `Error::SquadParsingError`, as the
+/// format is not understood.
+pub fn player_list(players_string: &str) -> Result<PlayerList, Error> {
+    let mut lines = players_string.split('\n').skip(1);
+
+    let players = section(active_lines(&mut lines), player, "player")?;
+    let disconnected = section(lines, disconnected_player, "disconnected player")?;
+
+    Ok(PlayerList::new(players, disconnected))
+}
+
+/// The lines of active players, leaving the recently disconnected players in
+/// `lines`
+fn active_lines<'a, I: Iterator<Item = &'a str>>(lines: &mut I) -> Vec<&'a str> {
+    lines
+        .take_while(|line| !line.contains("Recently Disconnected Players"))
+        .collect()
+}
+
+/// Parse each non-empty line of a section with `parse`, skipping and logging
+/// those which cannot be read, and failing if none can
+fn section<'a, I, T, F>(lines: I, parse: F, what: &str) -> Result<Vec<T>, Error>
+where
+    I: IntoIterator<Item = &'a str>,
+    F: Fn(&str) -> Option<T>,
+{
+    let mut parsed = Vec::new();
+    let mut skipped = 0;
+    for line in lines.into_iter().filter(|line| !line.trim().is_empty()) {
+        match parse(line) {
+            Some(item) => parsed.push(item),
+            None => {
+                warn!("Skipping unrecognised {} {:?}", what, line);
+                skipped += 1;
+            }
+        }
+    }
+    if parsed.is_empty() && skipped > 0 {
+        return Err(Error::SquadParsingError);
+    }
+    Ok(parsed)
+}
+
+fn player(line: &str) -> Option<Player> {
+    let fields = Fields::parse(line);
+    let (steam_id, eos_id) = fields.online_ids("");
+    if steam_id.is_none() && eos_id.is_none() {
+        return None;
+    }
+
+    let mut player = Player::new(
+        fields.id()?,
+        steam_id.unwrap_or_default(),
+        fields.get("Name")?.to_string(),
+        fields.optional_id("Team ID"),
+        fields.optional_id("Squad ID"),
+    );
+    if let Some(eos_id) = eos_id {
+        player = player.with_eos_id(eos_id);
+    }
+    if let Some(is_leader) = fields.bool("Is Leader") {
+        player = player.with_leader(is_leader);
+    }
+    if let Some(role) = fields.get("Role") {
+        player = player.with_role(role);
+    }
+    Some(player)
+}
+
+fn disconnected_player(line: &str) -> Option<DisconnectedPlayer> {
+    let fields = Fields::parse(line);
+    let (steam_id, eos_id) = fields.online_ids("");
+    if steam_id.is_none() && eos_id.is_none() {
+        return None;
+    }
+
+    let mut player = DisconnectedPlayer::new(
+        fields.id()?,
+        steam_id.unwrap_or_default(),
+        since_disconnect(fields.get("Since Disconnect")?).ok()?,
+        fields.get("Name")?.to_string(),
+    );
+    if let Some(eos_id) = eos_id {
+        player = player.with_eos_id(eos_id);
+    }
+    Some(player)
+}
+
+/// Parse a time since disconnecting, such as `02m.30s` or `1h.02m.30s`
+fn since_disconnect(since_string: &str) -> Result<Duration, Error> {
+    let mut seconds: u64 = 0;
+    for part in since_string.trim().split('.') {
+        let multiplier = match part.chars().last() {
+            Some('h') => 60 * 60,
+            Some('m') => 60,
+            Some('s') => 1,
+            _ => return Err(Error::SquadParsingError),
+        };
+        // The unit is one byte long
+        let number = part[..part.len() - 1].parse::<u64>()?;
+        seconds = number
+            .checked_mul(multiplier)
+            .and_then(|part_seconds| seconds.checked_add(part_seconds))
+            .ok_or(Error::SquadParsingError)?;
+    }
+    Ok(Duration::from_secs(seconds))
+}
+
+/// Parse the response to `ListSquads`.
+///
+/// Lines which cannot be read are skipped, and logged. If there are squad
+/// lines, but none can be read, this fails with `Error::SquadParsingError`.
+pub fn squads(squads_string: &str) -> Result<(Vec<Team>, Vec<Squad>), Error> {
+    let mut teams = Vec::new();
+    let mut squads = Vec::new();
+    let mut skipped = 0;
+
+    let mut current_team = 0;
+
+    for line in squads_string.split('\n').skip(1) {
+        if let Some(captures) = TEAM_REGEX.captures(line) {
+            let id = captures
+                .get(1)
+                .expect("squads team get 1")
+                .as_str()
+                .parse()
+                .unwrap_or(0);
+            let name = captures
+                .get(2)
+                .expect("squads team get 2")
+                .as_str()
+                .to_string();
+
+            current_team = id;
+            let team = Team::new(id, name);
+            teams.push(team);
+        } else if let Some(squad) = squad(line, current_team) {
+            squads.push(squad);
+        } else if !line.trim().is_empty() {
+            warn!("Skipping unrecognised squad {:?}", line);
+            skipped += 1;
+        }
+    }
+
+    if squads.is_empty() && skipped > 0 {
+        return Err(Error::SquadParsingError);
+    }
+    Ok((teams, squads))
+}
+
+fn squad(line: &str, team_id: usize) -> Option<Squad> {
+    let fields = Fields::parse(line);
+    let mut squad = Squad::new(
+        fields.id()?,
+        fields.get("Name")?.to_string(),
+        fields.optional_id("Size").unwrap_or(0),
+        team_id,
+        fields.bool("Locked").unwrap_or(false),
+    );
+    if let Some(creator_name) = fields.get("Creator Name") {
+        squad = squad.with_creator_name(creator_name);
+    }
+    let (steam_id, eos_id) = fields.online_ids("Creator ");
+    if let Some(steam_id) = steam_id {
+        squad = squad.with_creator_steam_id(steam_id);
+    }
+    if let Some(eos_id) = eos_id {
+        squad = squad.with_creator_eos_id(eos_id);
+    }
+    Some(squad)
+}
+
+/// Parse the response to `ListMaps` or `ListLayers`, skipping the
+/// "List of available layers :" header sent by newer servers
+pub fn list_maps(maps_string: &str) -> Vec<String> {
+    maps_string
+        .split('\n')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty() && !s.starts_with("List of available"))
+        .map(|s| s.to_string())
+        .collect::<Vec<String>>()
+}
+
+/// Parse the response to `ListMaps` or `ListLayers` into layers, skipping
+/// names which are not recognised
+pub fn list_layers(maps_string: &str) -> Vec<Layer> {
+    list_maps(maps_string)
+        .iter()
+        .filter_map(|name| Layer::parse(name).ok())
+        .collect()
+}
+
+/// Parse `(current_layer, next_layer)`, as returned by `layers`
+pub fn parsed_layers(
+    current: &LayerInfo,
+    next: Option<&LayerInfo>,
+) -> Result<(Layer, Option<Layer>), Error> {
+    Ok((
+        Layer::try_from(current)?,
+        next.map(Layer::try_from).transpose()?,
+    ))
+}
+
+/// Parse one `Current level is X, layer is Y, factions Z` response, as sent
+/// by newer servers to `ShowCurrentMap` and `ShowNextMap`. Returns `None` if
+/// no layer is set.
+fn layer_info(layer_string: &str) -> Result<Option<LayerInfo>, Error> {
+    let captures = LAYER_REGEX
+        .captures(layer_string.trim())
+        .ok_or(Error::SquadParsingError)?;
+
+    let layer = captures.get(2).expect("layer 2").as_str().trim();
+    if layer.is_empty() {
+        return Ok(None);
+    }
+    let level = captures.get(1).expect("layer 1").as_str().trim();
+    Ok(Some(LayerInfo::new(
+        Some(level.to_string()).filter(|level| !level.is_empty()),
+        layer.to_string(),
+        captures
+            .get(3)
+            .map(|factions| factions.as_str().trim().to_string()),
+    )))
+}
+
+/// Whether a response to `ShowNextMap` reports both the current and next
+/// map, as older servers do
+pub fn reports_both_maps(next_map_string: &str) -> bool {
+    MAPS_REGEX.is_match(next_map_string)
+}
+
+/// Parse the responses to `ShowCurrentMap` and `ShowNextMap` into
+/// `(current_layer, next_layer)`.
+///
+/// Older servers answer `ShowNextMap` with both the current and next map,
+/// and may not answer `ShowCurrentMap` at all, in which case its response is
+/// ignored.
+pub fn layers(
+    current_map_string: &str,
+    next_map_string: &str,
+) -> Result<(LayerInfo, Option<LayerInfo>), Error> {
+    if let Some(captures) = MAPS_REGEX.captures(next_map_string) {
+        let layer = |i| {
+            let name = captures.get(i).expect("layers").as_str().trim();
+            Some(LayerInfo::new(None, name.to_string(), None)).filter(|_| !name.is_empty())
+        };
+        return Ok((layer(1).ok_or(Error::SquadParsingError)?, layer(2)));
+    }
+
+    Ok((
+        layer_info(current_map_string)?.ok_or(Error::SquadParsingError)?,
+        layer_info(next_map_string)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    #[test]
+    fn layers() {
+        let (current, next) = super::layers(
+            "Current level is Narva, layer is Narva_RAAS_v1, factions USA RGF",
+            "Next level is Gorodok, layer is Gorodok_AAS_v2, factions USMC MEA\n",
+        )
+        .unwrap();
+        assert_eq!(current.level(), Some("Narva"));
+        assert_eq!(current.layer(), "Narva_RAAS_v1");
+        assert_eq!(current.factions(), Some("USA RGF"));
+        assert_eq!(next.unwrap().layer(), "Gorodok_AAS_v2");
+
+        let (current, next) = super::layers(
+            "",
+            "Current map is Narva_RAAS_v1, Next map is Gorodok_RAAS_v1",
+        )
+        .unwrap();
+        assert_eq!(current.layer(), "Narva_RAAS_v1");
+        assert_eq!(current.level(), None);
+        assert_eq!(next.unwrap().layer(), "Gorodok_RAAS_v1");
+
+        let (_, next) = super::layers(
+            "Current level is Narva, layer is Narva_RAAS_v1",
+            "Next level is , layer is ",
+        )
+        .unwrap();
+        assert!(next.is_none());
+        assert!(super::layers("", "").is_err());
+    }
+
+    #[test]
+    fn player_list() {
+        let player_list = super::player_list(
+            "----- Active Players -----
+ID: 0 | SteamID: 76561198000000001 | Name: Alice | Team ID: 1 | Squad ID: 1
+----- Recently Disconnected Players [Max of 15] -----
+ID: 7 | SteamID: 76561198000000003 | Since Disconnect: 02m.30s | Name: Carol
+",
+        )
+        .unwrap();
+        assert_eq!(player_list.players().len(), 1);
+        let disconnected = &player_list.disconnected()[0];
+        assert_eq!(disconnected.id(), 7);
+        assert_eq!(disconnected.steam_id(), "76561198000000003");
+        assert_eq!(disconnected.since_disconnect(), Duration::from_secs(150));
+        assert_eq!(disconnected.name(), "Carol");
+
+        assert_eq!(
+            super::since_disconnect("1h.00m.05s").unwrap(),
+            Duration::from_secs(3605)
+        );
+        assert!(super::since_disconnect("soon").is_err());
+        assert!(super::since_disconnect("5ä").is_err());
+        assert!(super::since_disconnect("10000000000000000000h").is_err());
+    }
+
+    #[test]
+    fn newer_formats() {
+        let player_list = super::player_list(
+            "----- Active Players -----
+ID: 0 | Online IDs: EOS: 0002abcdef steam: 76561198000000001 | Name: A | B | Team ID: 1 | Squad ID: 1 | Is Leader: True | Role: USA_SL_01 | Ping: 40
+ID: 1 | SteamID: 76561198000000002 | Name: Bob | Team ID: N/A | Squad ID: N/A | Is Leader: False | Role: USA_Rifleman_01
+not a player
+
+----- Recently Disconnected Players [Max of 15] -----
+ID: 7 | Online IDs: EOS: 0002fedcba | Since Disconnect: 01m.05s | Name: Carol
+",
+        )
+        .unwrap();
+        let players = player_list.players();
+        assert_eq!(players.len(), 2);
+        assert_eq!(players[0].name(), "A | B");
+        assert_eq!(players[0].steam_id(), "76561198000000001");
+        assert_eq!(players[0].eos_id(), Some("0002abcdef"));
+        assert_eq!(players[0].is_leader(), Some(true));
+        assert_eq!(players[0].role(), Some("USA_SL_01"));
+        assert_eq!(players[0].squad_id(), Some(1));
+        assert_eq!(players[1].eos_id(), None);
+        assert_eq!(players[1].team_id(), None);
+        assert_eq!(players[1].is_leader(), Some(false));
+        let disconnected = &player_list.disconnected()[0];
+        assert_eq!(disconnected.steam_id(), "");
+        assert_eq!(disconnected.eos_id(), Some("0002fedcba"));
+
+        let (teams, squads) = super::squads(
+            "----- Active Squads -----
+Team ID: 1 (United States Army)
+ID: 1 | Name: INF | Size: 12 | Locked: False | Creator Name: Alice | Creator Online IDs: EOS: 0002abcdef steam: 76561198000000001
+ID: 2 | Name: LOG | Size: 2 | Locked: True | Creator Name: Bob | Creator Steam ID: 76561198000000002
+",
+        )
+        .unwrap();
+        assert_eq!(teams.len(), 1);
+        assert_eq!(squads[0].size(), 12);
+        assert_eq!(squads[0].creator_name(), Some("Alice"));
+        assert_eq!(squads[0].creator_eos_id(), Some("0002abcdef"));
+        assert_eq!(squads[1].creator_steam_id(), Some("76561198000000002"));
+        assert!(squads[1].locked());
+    }
+
+    #[test]
+    fn unreadable_sections() {
+        assert!(super::player_list(
+            "----- Active Players -----
+Player 0 is Alice
+----- Recently Disconnected Players [Max of 15] -----"
+        )
+        .is_err());
+        assert!(super::player_list(
+            "----- Active Players -----
+----- Recently Disconnected Players [Max of 15] -----
+Carol left 2 minutes ago"
+        )
+        .is_err());
+        // The active players are still read
+        let players = super::players(
+            "----- Active Players -----
+ID: 0 | SteamID: 76561198000000001 | Name: Alice | Team ID: 1 | Squad ID: 1
+----- Recently Disconnected Players [Max of 15] -----
+Carol left 2 minutes ago",
+        )
+        .unwrap();
+        assert_eq!(players.len(), 1);
+        assert!(super::squads(
+            "----- Active Squads -----
+Team ID: 1 (United States Army)
+Squad 1 is INF"
+        )
+        .is_err());
+
+        // Empty sections are fine
+        let player_list = super::player_list(
+            "----- Active Players -----
+
+----- Recently Disconnected Players [Max of 15] -----
+",
+        )
+        .unwrap();
+        assert!(player_list.players().is_empty());
+        assert!(super::squads("----- Active Squads -----\n").is_ok());
+    }
+
+    #[test]
+    fn names_containing_fields() {
+        let players = super::players(
+            "----- Active Players -----
+ID: 0 | SteamID: 76561198000000001 | Name: A | Team ID: 1 | Squad ID: 1
+ID: 1 | SteamID: 76561198000000002 | Name: B | Key: C | Team ID: 2 | Squad ID: N/A | Ping: 40",
+        )
+        .unwrap();
+        assert_eq!(players[1].name(), "B | Key: C");
+        assert_eq!(players[1].team_id(), Some(2));
+    }
+
+    #[test]
+    fn list_layers() {
+        assert_eq!(
+            super::list_maps("List of available layers :\nNarva_RAAS_v1\nGorodok_AAS_v2\n"),
+            vec!["Narva_RAAS_v1", "Gorodok_AAS_v2"]
+        );
+    }
+}
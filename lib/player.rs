@@ -7,6 +7,9 @@ pub struct Player {
     name: String,
     team_id: Option<usize>,
     squad_id: Option<usize>,
+    eos_id: Option<String>,
+    is_leader: Option<bool>,
+    role: Option<String>,
 }
 
 impl Player {
@@ -18,17 +21,37 @@ impl Player {
         squad_id: Option<usize>,
     ) -> Player {
         Player {
-            id: id,
-            steam_id: steam_id,
-            name: name,
-            team_id: team_id,
-            squad_id: squad_id,
+            id,
+            steam_id,
+            name,
+            team_id,
+            squad_id,
+            eos_id: None,
+            is_leader: None,
+            role: None,
         }
     }
 
+    pub fn with_eos_id<S: Into<String>>(mut self, eos_id: S) -> Player {
+        self.eos_id = Some(eos_id.into());
+        self
+    }
+
+    pub fn with_leader(mut self, is_leader: bool) -> Player {
+        self.is_leader = Some(is_leader);
+        self
+    }
+
+    pub fn with_role<S: Into<String>>(mut self, role: S) -> Player {
+        self.role = Some(role.into());
+        self
+    }
+
     pub fn id(&self) -> usize {
         self.id
     }
+    /// The player's steam64id. This is empty for players the server only
+    /// lists with an EOS id.
     pub fn steam_id(&self) -> &str {
         &self.steam_id
     }
@@ -41,4 +64,23 @@ impl Player {
     pub fn squad_id(&self) -> Option<usize> {
         self.squad_id
     }
+    /// The player's Epic Online Services id, on servers which list it
+    pub fn eos_id(&self) -> Option<&str> {
+        self.eos_id.as_deref()
+    }
+    /// Whether the player leads their squad, on servers which list it
+    pub fn is_leader(&self) -> Option<bool> {
+        self.is_leader
+    }
+    /// The player's role, such as `USA_SL_01`, on servers which list it
+    pub fn role(&self) -> Option<&str> {
+        self.role.as_deref()
+    }
+}
+
+/// Admin commands which act on a player by id accept a `&Player` directly
+impl From<&Player> for usize {
+    fn from(player: &Player) -> usize {
+        player.id
+    }
 }
@@ -0,0 +1,33 @@
+use crate::{DisconnectedPlayer, Player};
+use serde::{Deserialize, Serialize};
+
+/// Everything listed by `ListPlayers`, as returned by
+/// `SquadRcon::player_list`
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PlayerList {
+    players: Vec<Player>,
+    disconnected: Vec<DisconnectedPlayer>,
+}
+
+impl PlayerList {
+    pub fn new(players: Vec<Player>, disconnected: Vec<DisconnectedPlayer>) -> PlayerList {
+        PlayerList {
+            players,
+            disconnected,
+        }
+    }
+
+    /// The players who are on the server
+    pub fn players(&self) -> &[Player] {
+        &self.players
+    }
+    /// The players who left the server recently. The server only remembers
+    /// the last 15.
+    pub fn disconnected(&self) -> &[DisconnectedPlayer] {
+        &self.disconnected
+    }
+
+    pub fn into_players(self) -> Vec<Player> {
+        self.players
+    }
+}
@@ -0,0 +1,154 @@
+use std::time::{Duration, Instant};
+
+/// The longest `TokenBucket::wait_time`, so a very low rate cannot overflow
+/// a `Duration`
+const MAX_WAIT: Duration = Duration::from_secs(60 * 60);
+
+/// The lowest `RateLimit::rate`, one command an hour
+const MIN_RATE: f64 = 1.0 / (60.0 * 60.0);
+
+/// How urgently a queued command should run. Queued commands of a higher
+/// priority run before any of a lower priority, and commands of the same
+/// priority run in the order they were queued.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// Messages to players and admins, such as `broadcast`
+    Informational,
+    /// Commands which only read server state, such as `players`
+    Query,
+    /// Actions against players, such as `kick`, `ban` and `warn`
+    Moderation,
+}
+
+/// A token bucket limiting how quickly commands are sent to the server.
+///
+/// Up to `burst` commands can be sent at once, after which commands are sent
+/// at `rate` per second.
+#[derive(Clone, Debug)]
+pub struct RateLimit {
+    burst: u32,
+    rate: f64,
+}
+
+impl Default for RateLimit {
+    fn default() -> RateLimit {
+        RateLimit {
+            burst: 10,
+            rate: 5.0,
+        }
+    }
+}
+
+impl RateLimit {
+    pub fn new() -> RateLimit {
+        RateLimit::default()
+    }
+
+    /// The number of commands which can be sent at once. Must be at least
+    /// one.
+    pub fn with_burst(mut self, burst: u32) -> RateLimit {
+        self.burst = burst.max(1);
+        self
+    }
+
+    /// The number of commands sent per second once the burst is used up.
+    /// Must be at least one an hour, and anything lower, or `NaN`, is raised
+    /// to that.
+    pub fn with_rate(mut self, rate: f64) -> RateLimit {
+        self.rate = if rate.is_nan() {
+            MIN_RATE
+        } else {
+            rate.clamp(MIN_RATE, f64::MAX)
+        };
+        self
+    }
+
+    pub fn burst(&self) -> u32 {
+        self.burst
+    }
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+}
+
+/// The state of a `RateLimit`
+pub(crate) struct TokenBucket {
+    rate_limit: RateLimit,
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl TokenBucket {
+    pub(crate) fn new(rate_limit: RateLimit) -> TokenBucket {
+        TokenBucket {
+            tokens: rate_limit.burst as f64,
+            rate_limit,
+            refilled: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled).as_secs_f64();
+        self.tokens =
+            (self.tokens + elapsed * self.rate_limit.rate).min(self.rate_limit.burst as f64);
+        self.refilled = now;
+    }
+
+    /// How long until a command may be sent
+    pub(crate) fn wait_time(&mut self) -> Duration {
+        self.refill();
+        if self.tokens >= 1.0 {
+            Duration::ZERO
+        } else {
+            Duration::try_from_secs_f64((1.0 - self.tokens) / self.rate_limit.rate)
+                .map_or(MAX_WAIT, |wait_time| wait_time.min(MAX_WAIT))
+        }
+    }
+
+    /// Use up a token for each command sent. Sending more commands than
+    /// there are tokens makes the next command wait longer.
+    pub(crate) fn take(&mut self, commands: u64) {
+        self.refill();
+        self.tokens -= commands as f64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Priority, RateLimit, TokenBucket};
+    use std::time::Duration;
+
+    #[test]
+    fn burst_then_rate() {
+        let mut bucket = TokenBucket::new(RateLimit::new().with_burst(2).with_rate(10.0));
+        for _ in 0..2 {
+            assert_eq!(bucket.wait_time(), Duration::ZERO);
+            bucket.take(1);
+        }
+        let wait_time = bucket.wait_time();
+        assert!(wait_time > Duration::from_millis(50), "{:?}", wait_time);
+        assert!(wait_time <= Duration::from_millis(100), "{:?}", wait_time);
+    }
+
+    #[test]
+    fn clamped_rate() {
+        for rate in [0.0, -1.0, 1e-320, f64::NAN] {
+            assert_eq!(RateLimit::new().with_rate(rate).rate(), super::MIN_RATE);
+        }
+        assert_eq!(RateLimit::new().with_rate(f64::INFINITY).rate(), f64::MAX);
+    }
+
+    #[test]
+    fn long_wait() {
+        let mut bucket = TokenBucket::new(RateLimit::new().with_burst(1).with_rate(1.0));
+        bucket.take(u64::MAX);
+        assert_eq!(bucket.wait_time(), super::MAX_WAIT);
+    }
+
+    #[test]
+    fn priority_order() {
+        assert!(Priority::Moderation > Priority::Query);
+        assert!(Priority::Query > Priority::Informational);
+    }
+}
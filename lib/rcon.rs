@@ -1,20 +1,38 @@
-use crate::Error;
+use crate::terminator::DETECTION_TIMEOUT;
+use crate::transport::{TcpTransport, Transport};
+use crate::{Error, RconConfig, Terminator};
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use log::debug;
 use serde::{Deserialize, Serialize};
-use std::io::{Read, Write};
-use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::collections::VecDeque;
+use std::io::{Cursor, Read};
+use std::net::ToSocketAddrs;
+use std::time::{Duration, Instant};
 
 pub const SERVERDATA_AUTH: i32 = 3;
 pub const SERVERDATA_AUTH_RESPONSE: i32 = 2;
 pub const SERVERDATA_EXECCOMMAND: i32 = 2;
 pub const SERVERDATA_RESPONSE_VALUE: i32 = 0;
 
+/// The size of the size, id and type fields which begin every packet
+pub const HEADER_SIZE: usize = 12;
+
+/// The smallest valid value of a packet's size field: the id and type fields,
+/// an empty body, and two null terminators
+pub const MIN_PACKET_SIZE: i32 = 10;
+
+/// The default limit on the body length of a received packet
+pub const DEFAULT_MAX_BODY_LENGTH: usize = 1024 * 1024;
+
 /// A packet as received per the Rcon protocol
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct RconPacket {
     id: i32,
     type_: i32,
     body: String,
+    /// The body as received, if it was not valid UTF-8
+    #[serde(default)]
+    raw_body: Option<Vec<u8>>,
 }
 
 impl RconPacket {
@@ -22,9 +40,10 @@ impl RconPacket {
         let body: String = body.into();
 
         RconPacket {
-            id: id,
-            type_: type_,
-            body: body,
+            id,
+            type_,
+            body,
+            raw_body: None,
         }
     }
 
@@ -38,62 +57,245 @@ impl RconPacket {
         &self.body
     }
 
+    /// The body exactly as it was received.
+    ///
+    /// This differs from `body` only if the body was not valid UTF-8, in
+    /// which case `body` has invalid sequences replaced with U+FFFD.
+    pub fn raw_body(&self) -> &[u8] {
+        match &self.raw_body {
+            Some(raw_body) => raw_body,
+            None => self.body.as_bytes(),
+        }
+    }
+
+    /// Returns true if the body was not valid UTF-8, and `body` is a lossy
+    /// conversion of `raw_body`.
+    pub fn is_lossy(&self) -> bool {
+        self.raw_body.is_some()
+    }
+
     pub fn encode(&self) -> Result<Vec<u8>, Error> {
         let mut data = Vec::new();
         data.write_i32::<LittleEndian>((self.body().len() + 10) as i32)?;
         data.write_i32::<LittleEndian>(self.id())?;
         data.write_i32::<LittleEndian>(self.type_())?;
-        data.append(
-            &mut self
-                .body()
-                .as_bytes()
-                .into_iter()
-                .map(|b| *b)
-                .collect::<Vec<u8>>(),
-        );
+        data.extend_from_slice(self.body().as_bytes());
         data.push(0);
         data.push(0);
         Ok(data)
     }
 
+    /// Decode the fixed-size header of a packet, which is the size, id and
+    /// type fields.
+    ///
+    /// Returns `(remaining, id, type_)`, where `remaining` is the number of
+    /// bytes which follow the header on the wire (the body plus its two null
+    /// terminators). Those bytes should be passed to `RconPacket::decode_body`.
+    ///
+    /// Fails with `Error::InvalidPacketSize` if the size field is too small to
+    /// be a packet, and with `Error::PacketTooLarge` if the body would be
+    /// longer than `max_body_length`.
+    pub fn decode_header(
+        header: &[u8; HEADER_SIZE],
+        max_body_length: usize,
+    ) -> Result<(usize, i32, i32), Error> {
+        let mut cursor = Cursor::new(&header[..]);
+        let size = cursor.read_i32::<LittleEndian>()?;
+        let id = cursor.read_i32::<LittleEndian>()?;
+        let type_ = cursor.read_i32::<LittleEndian>()?;
+
+        if size < MIN_PACKET_SIZE {
+            return Err(Error::InvalidPacketSize(size));
+        }
+        let body_length = (size - MIN_PACKET_SIZE) as usize;
+        if body_length > max_body_length {
+            return Err(Error::PacketTooLarge(body_length));
+        }
+
+        Ok((body_length + 2, id, type_))
+    }
+
+    /// Build a packet from a decoded header and the bytes which followed it.
+    ///
+    /// A body which is not valid UTF-8 is decoded lossily, and kept as
+    /// received in `raw_body`.
+    pub fn decode_body(id: i32, type_: i32, mut body: Vec<u8>) -> Result<RconPacket, Error> {
+        body.truncate(body.len().saturating_sub(2));
+        match String::from_utf8(body) {
+            Ok(body) => Ok(RconPacket::new(id, type_, body)),
+            Err(e) => {
+                let raw_body = e.into_bytes();
+                let mut packet = RconPacket::new(id, type_, String::from_utf8_lossy(&raw_body));
+                packet.raw_body = Some(raw_body);
+                Ok(packet)
+            }
+        }
+    }
+
+    /// Read one packet from `reader`, with a body of at most
+    /// `max_body_length` bytes.
+    ///
+    /// Fails with `Error::Disconnected` if `reader` ends before a whole packet
+    /// has been read.
+    pub fn decode<R: Read>(reader: &mut R, max_body_length: usize) -> Result<RconPacket, Error> {
+        let mut header = [0; HEADER_SIZE];
+        recv_buf(reader, &mut header)?;
+        let (remaining, id, type_) = RconPacket::decode_header(&header, max_body_length)?;
+
+        let mut body = vec![0; remaining];
+        recv_buf(reader, &mut body)?;
+
+        RconPacket::decode_body(id, type_, body)
+    }
+
     pub fn into_body(self) -> String {
         self.body
     }
 }
 
+fn recv_buf<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<(), Error> {
+    let mut total_bytes_read = 0;
+    while total_bytes_read < buf.len() {
+        let bytes_read = reader.read(&mut buf[total_bytes_read..])?;
+        if bytes_read == 0 {
+            return Err(Error::Disconnected);
+        }
+        total_bytes_read += bytes_read;
+    }
+    Ok(())
+}
+
 /// An rcon client, implemented accordint to the rcon specification.
 ///
-/// This client has some minor squad specifics.
-pub struct RconClient {
+/// This client has some minor squad specifics. It runs over TCP by default,
+/// but can be used over any `Transport`.
+pub struct RconClient<T: Transport = TcpTransport> {
     next_id: i32,
     password: String,
-    stream: TcpStream,
-    addr: SocketAddr,
+    stream: T,
+    config: RconConfig,
+    terminator: Terminator,
+    /// Ids of commands which timed out, whose responses may still arrive
+    abandoned_ids: VecDeque<i32>,
+    /// Packets which arrived while detecting the terminator, returned with
+    /// the next response
+    early_packets: Vec<RconPacket>,
 }
 
-impl RconClient {
-    /// Connect to, and authenticate with, an rcon server
+/// How many timed out command ids are remembered, to discard their late
+/// responses
+const MAX_ABANDONED_IDS: usize = 64;
+
+impl RconClient<TcpTransport> {
+    /// Connect to, and authenticate with, an rcon server.
+    ///
+    /// See `TcpTransport::connect` for how `addr` is resolved.
     pub fn connect<A: ToSocketAddrs, S: Into<String>>(
         addr: A,
         password: S,
     ) -> Result<RconClient, Error> {
-        let addr: SocketAddr = addr.to_socket_addrs()?.next().unwrap();
+        RconClient::connect_with_config(addr, password, RconConfig::default())
+    }
+
+    /// Connect to, and authenticate with, an rcon server, with the given
+    /// timeouts and settings
+    pub fn connect_with_config<A: ToSocketAddrs, S: Into<String>>(
+        addr: A,
+        password: S,
+        config: RconConfig,
+    ) -> Result<RconClient, Error> {
+        let transport = TcpTransport::connect(addr, config.connect_timeout())?;
+        RconClient::from_transport(transport, password, config)
+    }
+
+    /// Connect to, and authenticate with, an rcon server at `host`, such as
+    /// `"example.com:21114"`. Unlike `RconClient::connect_with_config`, the
+    /// host is resolved again each time the client reconnects.
+    pub fn connect_host<H: Into<String>, S: Into<String>>(
+        host: H,
+        password: S,
+        config: RconConfig,
+    ) -> Result<RconClient, Error> {
+        let transport = TcpTransport::connect_host(host, config.connect_timeout())?;
+        RconClient::from_transport(transport, password, config)
+    }
+}
+
+impl<T: Transport> RconClient<T> {
+    /// Authenticate with an rcon server over an already open transport
+    pub fn from_transport<S: Into<String>>(
+        transport: T,
+        password: S,
+        config: RconConfig,
+    ) -> Result<RconClient<T>, Error> {
+        transport.set_read_timeout(config.read_timeout())?;
+        transport.set_write_timeout(config.write_timeout())?;
         let mut rcon_client = RconClient {
             next_id: 10,
             password: password.into(),
-            stream: TcpStream::connect(&addr)?,
-            addr: addr,
+            stream: transport,
+            terminator: config.terminator(),
+            config,
+            abandoned_ids: VecDeque::new(),
+            early_packets: Vec::new(),
         };
         rcon_client.authenticate()?;
+        rcon_client.terminator = rcon_client.detect_terminator()?;
         Ok(rcon_client)
     }
 
     pub fn reconnect(&mut self) -> Result<(), Error> {
-        self.stream = TcpStream::connect(&self.addr)?;
+        self.stream.shutdown().ok();
+        let transport = self.stream.reconnect()?;
+        transport.set_read_timeout(self.config.read_timeout())?;
+        transport.set_write_timeout(self.config.write_timeout())?;
+        self.stream = transport;
+        self.abandoned_ids.clear();
         self.authenticate()?;
+        self.terminator = self.detect_terminator()?;
         Ok(())
     }
 
+    /// The settings this client was connected with
+    pub fn config(&self) -> &RconConfig {
+        &self.config
+    }
+
+    /// How the end of each response is recognised. Once connected, this is
+    /// never `Terminator::Auto`.
+    pub fn terminator(&self) -> Terminator {
+        self.terminator
+    }
+
+    /// Resolve `Terminator::Auto` by checking whether the server answers an
+    /// empty SERVERDATA_RESPONSE_VALUE packet
+    fn detect_terminator(&mut self) -> Result<Terminator, Error> {
+        if self.config.terminator() != Terminator::Auto {
+            return Ok(self.config.terminator());
+        }
+
+        let probe_id = self.get_next_id();
+        self.send_packet(&Terminator::EmptyResponse.probe(probe_id).unwrap())?;
+
+        let detection_timeout = match self.config.read_timeout() {
+            Some(read_timeout) => read_timeout.min(DETECTION_TIMEOUT),
+            None => DETECTION_TIMEOUT,
+        };
+        self.stream.set_read_timeout(Some(detection_timeout))?;
+        let terminator = loop {
+            match self.recv_packet() {
+                Ok(packet) if packet.id() == probe_id => break Ok(Terminator::EmptyResponse),
+                Ok(packet) => self.early_packets.push(packet),
+                Err(Error::Timeout) => break Ok(Terminator::ShowNextMap),
+                Err(e) => break Err(e),
+            }
+        };
+        self.stream.set_read_timeout(self.config.read_timeout())?;
+
+        debug!("Using {:?} to terminate responses", terminator);
+        terminator
+    }
+
     /// Authenticate with the rcon server.
     ///
     /// This is automatically called by `RconClient::connect`
@@ -124,39 +326,9 @@ impl RconClient {
         Ok(())
     }
 
-    fn recv_buf(&mut self, buf_size: usize) -> Result<Vec<u8>, Error> {
-        let mut bytes = vec![0; buf_size];
-        let mut total_bytes_read = 0;
-        while total_bytes_read < buf_size {
-            let bytes_read = self.stream.read(&mut bytes[total_bytes_read..buf_size])?;
-            if bytes_read == 0 {
-                return Err(Error::Disconnected);
-            }
-            total_bytes_read += bytes_read;
-        }
-        Ok(bytes)
-    }
-
-    fn recv_i32(&mut self) -> Result<i32, Error> {
-        use std::io::Cursor;
-        Ok(Cursor::new(self.recv_buf(4)?).read_i32::<LittleEndian>()?)
-    }
-
     /// Receive an rcon packet from the server
     pub fn recv_packet(&mut self) -> Result<RconPacket, Error> {
-        let size = self.recv_i32()?;
-        let id = self.recv_i32()?;
-        let type_ = self.recv_i32()?;
-
-        let body = self.recv_buf(size as usize - 10)?;
-
-        let _null_bytes = self.recv_buf(2)?;
-
-        let body = String::from_utf8(body)?;
-
-        let packet = RconPacket::new(id, type_, body);
-
-        Ok(packet)
+        RconPacket::decode(&mut self.stream, self.config.max_body_length())
     }
 
     /// Send an rcon packet and wait for the response
@@ -165,6 +337,13 @@ impl RconClient {
         self.recv_packet()
     }
 
+    /// Take apart this client, returning the underlying transport, the next
+    /// id which would have been used, and any packets received but not yet
+    /// returned.
+    pub(crate) fn into_parts(self) -> (T, i32, Vec<RconPacket>) {
+        (self.stream, self.next_id, self.early_packets)
+    }
+
     /// Get the next id for an rcon packet to send
     pub fn get_next_id(&mut self) -> i32 {
         let next_id = self.next_id;
@@ -180,7 +359,7 @@ impl RconClient {
         &self.password
     }
 
-    /// Execute an rcon command, and return the entire response from the server
+    // Execute an rcon command, and return the entire response from the server
     // pub fn exec_command<S: Into<String>>(&mut self, command: S) -> Result<String, Error> {
     //     let mut body_parts: Vec<String> = Vec::new();
 
@@ -213,36 +392,388 @@ impl RconClient {
     /// Execute an rcon command, and return the entire response from the server,
     /// as well as any other packets which were on the line (these are most
     /// likely chat messages).
+    ///
+    /// The end of the response is recognised per `RconClient::terminator`.
     pub fn exec_command2<S: Into<String>>(
         &mut self,
         command: S,
     ) -> Result<(String, Vec<RconPacket>), Error> {
-        let mut body_parts: Vec<String> = Vec::new();
+        let (mut responses, other_packets) = self.exec_commands(&[command.into()])?;
+        Ok((responses.remove(0), other_packets))
+    }
 
-        let request_id = self.get_next_id();
-        let chk_id = self.get_next_id();
+    /// Execute several rcon commands at once, and return each response, in
+    /// the same order as `commands`, as well as any other packets which were
+    /// on the line.
+    ///
+    /// Every command is sent before any response is read, so this takes
+    /// about one round trip, however many commands there are. Responses are
+    /// matched to commands by packet id.
+    ///
+    /// The commands must be answered within the configured command timeout,
+    /// if there is one.
+    pub fn exec_commands<S: AsRef<str>>(
+        &mut self,
+        commands: &[S],
+    ) -> Result<(Vec<String>, Vec<RconPacket>), Error> {
+        self.exec_commands_with_timeout(commands, self.config.command_timeout())
+    }
 
-        let request_packet = RconPacket::new(request_id, SERVERDATA_EXECCOMMAND, command.into());
+    /// Execute several rcon commands at once, which must be answered within
+    /// `timeout`, or as long as the server keeps sending within the read
+    /// timeout if it is `None`.
+    ///
+    /// If they are not answered in time, this fails with
+    /// `Error::CommandTimeout`, and the connection remains usable. Responses
+    /// which arrive later are discarded.
+    pub fn exec_commands_with_timeout<S: AsRef<str>>(
+        &mut self,
+        commands: &[S],
+        timeout: Option<Duration>,
+    ) -> Result<(Vec<String>, Vec<RconPacket>), Error> {
+        if commands.is_empty() {
+            return Ok((Vec::new(), Vec::new()));
+        }
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+        let mut request_ids = Vec::with_capacity(commands.len());
+        for command in commands {
+            let request_id = self.get_next_id();
+            self.send_packet(&RconPacket::new(
+                request_id,
+                SERVERDATA_EXECCOMMAND,
+                command.as_ref(),
+            ))?;
+            request_ids.push(request_id);
+        }
 
-        // We just do this to make sure we've received all the data in the current packet
-        let check_packet = RconPacket::new(chk_id, SERVERDATA_EXECCOMMAND, "ShowNextMap");
+        let result = match self.terminator {
+            Terminator::Quiet(quiet) => self.recv_until_quiet(&request_ids, quiet, deadline),
+            terminator => {
+                // The server answers in order, so once the terminator is
+                // answered, every command before it has been too
+                let chk_id = self.get_next_id();
+                request_ids.push(chk_id);
+                self.send_packet(&terminator.probe(chk_id).unwrap())?;
+                self.recv_until(&request_ids[..commands.len()], chk_id, deadline)
+            }
+        };
+        if let Err(Error::CommandTimeout) = result {
+            self.abandon(&request_ids);
+        }
 
-        self.send_packet(&request_packet)?;
-        self.send_packet(&check_packet)?;
+        let (body_parts, mut other_packets) = result?;
+        other_packets.splice(0..0, self.early_packets.drain(..));
+        let responses = body_parts
+            .into_iter()
+            .map(|body_parts| body_parts.join(""))
+            .collect();
+        Ok((responses, other_packets))
+    }
 
-        let mut other_packets = Vec::new();
+    /// Remember ids whose responses should be discarded if they arrive late
+    fn abandon(&mut self, ids: &[i32]) {
+        self.abandoned_ids.extend(ids);
+        while self.abandoned_ids.len() > MAX_ABANDONED_IDS {
+            self.abandoned_ids.pop_front();
+        }
+    }
+
+    /// How long to wait for the next packet: `wait`, or the read timeout if
+    /// that is `None`, but never past `deadline`
+    fn wait_time(&self, wait: Option<Duration>, deadline: Option<Instant>) -> Option<Duration> {
+        let wait = wait.or_else(|| self.config.read_timeout());
+        match deadline {
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                Some(wait.map_or(remaining, |wait| wait.min(remaining)))
+            }
+            None => wait,
+        }
+    }
 
+    /// Receive a packet, waiting at most `wait` for it to begin, or forever
+    /// if `wait` is `None`. Returns `None` if nothing arrived in time.
+    ///
+    /// Once a packet has begun, the rest of it is read with the usual read
+    /// timeout, so a short `wait` never leaves the connection part way
+    /// through a packet.
+    fn recv_packet_within(&mut self, wait: Option<Duration>) -> Result<Option<RconPacket>, Error> {
+        if wait == Some(Duration::ZERO) {
+            return Ok(None);
+        }
+
+        let mut first = [0; 1];
+        self.stream.set_read_timeout(wait)?;
+        let read = self.stream.read(&mut first);
+        self.stream.set_read_timeout(self.config.read_timeout())?;
+        match read.map_err(Error::from) {
+            Ok(0) => Err(Error::Disconnected),
+            Ok(_) => {
+                let mut reader = (&first[..]).chain(&mut self.stream);
+                RconPacket::decode(&mut reader, self.config.max_body_length()).map(Some)
+            }
+            Err(Error::Timeout) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The error for a wait which ended without a packet
+    fn timeout_error(deadline: Option<Instant>) -> Error {
+        match deadline {
+            Some(deadline) if Instant::now() >= deadline => Error::CommandTimeout,
+            _ => Error::Timeout,
+        }
+    }
+
+    /// Sort a received packet which is not part of a response into
+    /// `other_packets`, unless it is a late response to an abandoned command
+    fn other_packet(&self, packet: RconPacket, other_packets: &mut Vec<RconPacket>) {
+        if self.abandoned_ids.contains(&packet.id()) {
+            debug!("Discarding late response with id {}", packet.id());
+        } else {
+            other_packets.push(packet);
+        }
+    }
+
+    /// Receive the responses to `request_ids`, up to the answer to `chk_id`
+    fn recv_until(
+        &mut self,
+        request_ids: &[i32],
+        chk_id: i32,
+        deadline: Option<Instant>,
+    ) -> Result<(Vec<Vec<String>>, Vec<RconPacket>), Error> {
+        let mut body_parts = vec![Vec::new(); request_ids.len()];
+        let mut other_packets = Vec::new();
         loop {
-            let response = self.recv_packet()?;
-            if response.id() == request_id {
-                body_parts.push(response.into_body());
+            let wait = self.wait_time(None, deadline);
+            let response = match self.recv_packet_within(wait)? {
+                Some(response) => response,
+                None => return Err(RconClient::<T>::timeout_error(deadline)),
+            };
+            if let Some(i) = request_ids.iter().position(|id| *id == response.id()) {
+                body_parts[i].push(response.into_body());
             } else if response.id() == chk_id {
-                break;
+                return Ok((body_parts, other_packets));
             } else {
-                other_packets.push(response);
+                self.other_packet(response, &mut other_packets);
             }
         }
+    }
+
+    /// Receive the responses to `request_ids`, until the server has been
+    /// quiet for `quiet` after answering any of them. Requests which were not
+    /// answered by then get an empty response, and their ids are abandoned.
+    ///
+    /// If nothing at all arrives within the read timeout, this fails with
+    /// `Error::Timeout`, as the connection may have been lost.
+    fn recv_until_quiet(
+        &mut self,
+        request_ids: &[i32],
+        quiet: Duration,
+        deadline: Option<Instant>,
+    ) -> Result<(Vec<Vec<String>>, Vec<RconPacket>), Error> {
+        let mut body_parts: Vec<Vec<String>> = vec![Vec::new(); request_ids.len()];
+        let mut other_packets = Vec::new();
+        loop {
+            let answered = body_parts.iter().any(|body_parts| !body_parts.is_empty());
+            let wait = self.wait_time(if answered { Some(quiet) } else { None }, deadline);
+            let response = match self.recv_packet_within(wait)? {
+                Some(response) => response,
+                None if !answered => return Err(RconClient::<T>::timeout_error(deadline)),
+                None => match RconClient::<T>::timeout_error(deadline) {
+                    Error::CommandTimeout => return Err(Error::CommandTimeout),
+                    _ => break,
+                },
+            };
+            match request_ids.iter().position(|id| *id == response.id()) {
+                Some(i) => body_parts[i].push(response.into_body()),
+                None => self.other_packet(response, &mut other_packets),
+            }
+        }
+
+        let unanswered: Vec<i32> = request_ids
+            .iter()
+            .zip(&body_parts)
+            .filter(|(_, body_parts)| body_parts.is_empty())
+            .map(|(id, _)| *id)
+            .collect();
+        self.abandon(&unanswered);
+        Ok((body_parts, other_packets))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RconPacket, HEADER_SIZE};
+    use crate::mock::fixtures::{server, PLAYERS, SQUADS};
+    use crate::{Error, RconClient, RconConfig, Terminator};
+    use proptest::prelude::*;
+    use std::thread;
+    use std::time::Duration;
+
+    proptest! {
+        #[test]
+        fn round_trip(id in any::<i32>(), type_ in any::<i32>(), body in ".{0,512}") {
+            let data = RconPacket::new(id, type_, body.clone()).encode().unwrap();
+            let packet = RconPacket::decode(&mut &data[..], 4096).unwrap();
+
+            prop_assert_eq!(packet.id(), id);
+            prop_assert_eq!(packet.type_(), type_);
+            prop_assert_eq!(packet.body(), body.as_str());
+            prop_assert!(!packet.is_lossy());
+        }
+
+        #[test]
+        fn round_trip_stream(bodies in prop::collection::vec(".{0,64}", 0..8)) {
+            let mut data = Vec::new();
+            for (id, body) in bodies.iter().enumerate() {
+                data.extend(RconPacket::new(id as i32, 0, body.clone()).encode().unwrap());
+            }
+
+            let mut reader = &data[..];
+            for (id, body) in bodies.iter().enumerate() {
+                let packet = RconPacket::decode(&mut reader, 4096).unwrap();
+                prop_assert_eq!(packet.id(), id as i32);
+                prop_assert_eq!(packet.body(), body.as_str());
+            }
+            prop_assert!(reader.is_empty());
+        }
+
+        #[test]
+        fn arbitrary_bytes(data in prop::collection::vec(any::<u8>(), 0..256)) {
+            // Must never panic, whatever the server sends
+            let mut reader = &data[..];
+            while RconPacket::decode(&mut reader, 64).is_ok() {}
+        }
+    }
+
+    #[test]
+    fn truncated_packet() {
+        let data = RconPacket::new(12, 0, "ListPlayers").encode().unwrap();
+        for length in 0..data.len() {
+            match RconPacket::decode(&mut &data[..length], 4096) {
+                Err(Error::Disconnected) => {}
+                other => panic!("length {}: {:?}", length, other),
+            }
+        }
+    }
+
+    #[test]
+    fn oversized_frame() {
+        // The header claims a body far larger than the data which follows.
+        // This must fail before reading, or allocating, the body.
+        let mut data = header(i32::MAX).to_vec();
+        data.extend_from_slice(b"short");
+        match RconPacket::decode(&mut &data[..], 4096) {
+            Err(Error::PacketTooLarge(length)) => assert_eq!(length, i32::MAX as usize - 10),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    fn header(size: i32) -> [u8; HEADER_SIZE] {
+        let mut header = [0; HEADER_SIZE];
+        header[0..4].copy_from_slice(&size.to_le_bytes());
+        header
+    }
+
+    #[test]
+    fn undersized_packet() {
+        for size in &[-1, 0, 9, i32::MIN] {
+            match RconPacket::decode_header(&header(*size), 4096) {
+                Err(Error::InvalidPacketSize(s)) => assert_eq!(s, *size),
+                other => panic!("size {}: {:?}", size, other),
+            }
+        }
+        assert_eq!(RconPacket::decode_header(&header(10), 4096).unwrap().0, 2);
+    }
+
+    #[test]
+    fn oversized_packet() {
+        match RconPacket::decode_header(&header(i32::MAX), 4096) {
+            Err(Error::PacketTooLarge(_)) => {}
+            other => panic!("{:?}", other),
+        }
+        match RconPacket::decode_header(&header(4097 + 10), 4096) {
+            Err(Error::PacketTooLarge(4097)) => {}
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(
+            RconPacket::decode_header(&header(4096 + 10), 4096)
+                .unwrap()
+                .0,
+            4098
+        );
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        let mut data = RconPacket::new(11, 0, "Name: ab").encode().unwrap();
+        data[HEADER_SIZE + 7] = 0xff;
+
+        let packet = RconPacket::decode(&mut &data[..], 4096).unwrap();
+        assert!(packet.is_lossy());
+        assert_eq!(packet.body(), "Name: a\u{fffd}");
+        assert_eq!(packet.raw_body(), b"Name: a\xff");
+        assert_eq!(packet.id(), 11);
+    }
+
+    #[test]
+    fn quiet_partial_results() {
+        let server = server();
+        server.ignore_command("ShowCurrentMap");
+        let config = RconConfig::new()
+            .with_read_timeout(Some(Duration::from_millis(500)))
+            .with_terminator(Terminator::Quiet(Duration::from_millis(50)));
+        let commands = ["ListPlayers", "ShowCurrentMap", "ListSquads"];
+
+        let mut rcon_client =
+            RconClient::connect_with_config(server.addr(), "password", config).unwrap();
+        let (responses, _) = rcon_client.exec_commands(&commands).unwrap();
+        assert_eq!(responses, vec![PLAYERS, "", SQUADS]);
+        assert_eq!(rcon_client.exec_command2("ListSquads").unwrap().0, SQUADS);
+    }
+
+    #[test]
+    fn terminator_detection() {
+        let server = server();
+        let config = RconConfig::new().with_terminator(Terminator::Auto);
+        let mut rcon_client =
+            RconClient::connect_with_config(server.addr(), "password", config).unwrap();
+        assert_eq!(rcon_client.terminator(), Terminator::EmptyResponse);
+        rcon_client.exec_command2("ListPlayers").unwrap();
+        assert_eq!(server.received_commands(), vec!["ListPlayers"]);
+
+        server.set_ignore_empty_response(true);
+        let config = RconConfig::new()
+            .with_read_timeout(Some(Duration::from_millis(100)))
+            .with_terminator(Terminator::Auto);
+        let mut rcon_client =
+            RconClient::connect_with_config(server.addr(), "password", config).unwrap();
+        assert_eq!(rcon_client.terminator(), Terminator::ShowNextMap);
+        assert_eq!(
+            rcon_client.exec_command2("ListMaps").unwrap().0,
+            "Narva_RAAS_v1\nGorodok_RAAS_v1"
+        );
+    }
 
-        Ok((body_parts.join(""), other_packets))
+    #[test]
+    fn events_during_detection() {
+        let server = server();
+        server.set_ignore_empty_response(true);
+        let config = RconConfig::new()
+            .with_read_timeout(Some(Duration::from_secs(1)))
+            .with_terminator(Terminator::Auto);
+
+        let addr = server.addr();
+        let connecting =
+            thread::spawn(move || RconClient::connect_with_config(addr, "password", config));
+        // Arrives while the client waits for an answer to its probe
+        thread::sleep(Duration::from_millis(200));
+        server.inject_chat("Something new happened").unwrap();
+        let mut rcon_client = connecting.join().unwrap().unwrap();
+
+        let (_, other_packets) = rcon_client.exec_command2("ListPlayers").unwrap();
+        assert_eq!(other_packets[0].body(), "Something new happened");
     }
 }
@@ -0,0 +1,299 @@
+use crate::Error;
+use lazy_static::lazy_static;
+use log::warn;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+lazy_static! {
+    /// The state of the jitter generator, seeded once per process
+    static ref JITTER_STATE: AtomicU64 = AtomicU64::new(seed());
+}
+
+/// A seed which differs between processes, so clients restarted together do
+/// not reconnect in lockstep
+fn seed() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    if let Ok(now) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        hasher.write_u128(now.as_nanos());
+    }
+    hasher.write_u32(std::process::id());
+    // Xorshift never leaves zero
+    hasher.finish() | 1
+}
+
+/// A random number between -1 and 1, from an xorshift generator. This only
+/// spreads out reconnection attempts, so it need not be strong.
+fn random() -> f64 {
+    let step = |mut x: u64| {
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        x
+    };
+    let previous = JITTER_STATE
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |x| Some(step(x)))
+        .expect("JITTER_STATE");
+    (step(previous) as f64 / u64::MAX as f64) * 2.0 - 1.0
+}
+
+/// How `SquadRcon` reconnects after losing its connection to the server.
+///
+/// Backoff between attempts grows exponentially from `initial_backoff` up to
+/// `max_backoff`, with up to `jitter` (a fraction of the backoff) added or
+/// removed at random.
+#[derive(Clone, Debug)]
+pub struct ReconnectPolicy {
+    max_attempts: usize,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    jitter: f64,
+    deadline: Option<Duration>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> ReconnectPolicy {
+        ReconnectPolicy {
+            max_attempts: 10,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            jitter: 0.2,
+            deadline: Some(Duration::from_secs(120)),
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    pub fn new() -> ReconnectPolicy {
+        ReconnectPolicy::default()
+    }
+
+    /// The maximum number of reconnection attempts. Must be at least one.
+    pub fn with_max_attempts(mut self, max_attempts: usize) -> ReconnectPolicy {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// The time to wait after the first failed attempt
+    pub fn with_initial_backoff(mut self, initial_backoff: Duration) -> ReconnectPolicy {
+        self.initial_backoff = initial_backoff;
+        self
+    }
+
+    /// The longest time to wait between two attempts
+    pub fn with_max_backoff(mut self, max_backoff: Duration) -> ReconnectPolicy {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// The fraction of each backoff, between 0 and 1, which is randomized
+    pub fn with_jitter(mut self, jitter: f64) -> ReconnectPolicy {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// The total time after which no more attempts are made, or `None` to
+    /// only be bounded by `max_attempts`
+    pub fn with_deadline(mut self, deadline: Option<Duration>) -> ReconnectPolicy {
+        self.deadline = deadline;
+        self
+    }
+
+    pub fn max_attempts(&self) -> usize {
+        self.max_attempts
+    }
+    pub fn initial_backoff(&self) -> Duration {
+        self.initial_backoff
+    }
+    pub fn max_backoff(&self) -> Duration {
+        self.max_backoff
+    }
+    pub fn jitter(&self) -> f64 {
+        self.jitter
+    }
+    pub fn deadline(&self) -> Option<Duration> {
+        self.deadline
+    }
+
+    /// The time to wait after the given failed attempt, starting from 1
+    pub fn backoff(&self, attempt: usize) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(31) as u32;
+        let backoff = self
+            .initial_backoff
+            .checked_mul(1 << exponent)
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff);
+
+        backoff.mul_f64(1.0 + self.jitter * random())
+    }
+
+    /// Call `connect` until it succeeds, or this policy gives up, in which
+    /// case the last error is returned.
+    pub(crate) fn retry<F>(&self, mut connect: F) -> Result<(), Error>
+    where
+        F: FnMut() -> Result<(), Error>,
+    {
+        let start = Instant::now();
+        let mut attempt = 0;
+        loop {
+            let error = match connect() {
+                Ok(()) => return Ok(()),
+                Err(error) => error,
+            };
+            attempt += 1;
+            warn!("Reconnection attempt {} failed: {}", attempt, error);
+
+            if attempt >= self.max_attempts {
+                return Err(error);
+            }
+            let backoff = self.backoff(attempt);
+            if let Some(deadline) = self.deadline {
+                if start.elapsed() + backoff > deadline {
+                    return Err(error);
+                }
+            }
+            std::thread::sleep(backoff);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ReconnectPolicy;
+    use crate::mock::fixtures::server;
+    use crate::{Error, RconConfig, SquadRcon, Terminator};
+    use std::time::Duration;
+
+    #[test]
+    fn backoff() {
+        let policy = ReconnectPolicy::new()
+            .with_initial_backoff(Duration::from_secs(1))
+            .with_max_backoff(Duration::from_secs(5))
+            .with_jitter(0.0);
+
+        assert_eq!(policy.backoff(1), Duration::from_secs(1));
+        assert_eq!(policy.backoff(2), Duration::from_secs(2));
+        assert_eq!(policy.backoff(3), Duration::from_secs(4));
+        assert_eq!(policy.backoff(4), Duration::from_secs(5));
+        assert_eq!(policy.backoff(100), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn jitter() {
+        let policy = ReconnectPolicy::new()
+            .with_initial_backoff(Duration::from_secs(10))
+            .with_max_backoff(Duration::from_secs(10_000))
+            .with_jitter(0.5);
+
+        let mut backoffs = Vec::new();
+        for attempt in 1..12 {
+            let backoff = policy.backoff(attempt);
+            let base = Duration::from_secs(10 << (attempt - 1)).min(policy.max_backoff());
+            assert!(backoff >= base / 2, "attempt {}: {:?}", attempt, backoff);
+            assert!(
+                backoff <= base * 3 / 2,
+                "attempt {}: {:?}",
+                attempt,
+                backoff
+            );
+            backoffs.push(policy.backoff(1));
+        }
+        // The jitter actually varies
+        assert!(backoffs.iter().any(|backoff| *backoff != backoffs[0]));
+    }
+
+    #[test]
+    fn reconnect_and_retry_query() {
+        let server = server();
+        let mut squad_rcon = SquadRcon::connect(server.addr(), "password").unwrap();
+        squad_rcon.set_reconnect_policy(Some(
+            ReconnectPolicy::new().with_initial_backoff(Duration::from_millis(10)),
+        ));
+
+        server.disconnect_all();
+        assert_eq!(squad_rcon.players().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn reconnect_to_host() {
+        let server = server();
+        let host = format!("localhost:{}", server.addr().port());
+        let mut squad_rcon =
+            SquadRcon::connect_host(host, "password", RconConfig::default()).unwrap();
+        squad_rcon.set_reconnect_policy(Some(
+            ReconnectPolicy::new().with_initial_backoff(Duration::from_millis(10)),
+        ));
+
+        server.disconnect_all();
+        assert_eq!(squad_rcon.players().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn reconnect_to_silent_quiet_server() {
+        let server = server();
+        let config = RconConfig::new()
+            .with_read_timeout(Some(Duration::from_millis(200)))
+            .with_terminator(Terminator::Quiet(Duration::from_millis(50)));
+        for threaded in [false, true] {
+            let mut squad_rcon = if threaded {
+                SquadRcon::connect_threaded_with_config(server.addr(), "password", config.clone())
+                    .unwrap()
+                    .0
+            } else {
+                SquadRcon::connect_with_config(server.addr(), "password", config.clone()).unwrap()
+            };
+            squad_rcon.set_reconnect_policy(Some(
+                ReconnectPolicy::new()
+                    .with_max_attempts(1)
+                    .with_initial_backoff(Duration::from_millis(10)),
+            ));
+
+            // The query is only sent again once the client has reconnected
+            server.set_silent(true);
+            match squad_rcon.players() {
+                Err(Error::Timeout) => {}
+                other => panic!("threaded: {}, {:?}", threaded, other),
+            }
+            server.set_silent(false);
+            let commands = server.received_commands();
+            assert_eq!(
+                commands[commands.len() - 2..],
+                ["ListPlayers", "ListPlayers"],
+                "threaded: {}",
+                threaded
+            );
+        }
+    }
+
+    #[test]
+    fn reconnect_without_retrying_commands() {
+        let server = server();
+        let mut squad_rcon = SquadRcon::connect(server.addr(), "password").unwrap();
+        squad_rcon.set_reconnect_policy(Some(
+            ReconnectPolicy::new().with_initial_backoff(Duration::from_millis(10)),
+        ));
+
+        server.disconnect_all();
+        assert!(squad_rcon.ban("Bob", "1d", "cheating").is_err());
+        assert!(!server
+            .received_commands()
+            .iter()
+            .any(|command| command.starts_with("AdminBan")));
+        assert_eq!(squad_rcon.maps().unwrap().0, "Narva_RAAS_v1");
+    }
+
+    #[test]
+    fn reconnect_gives_up() {
+        let server = server();
+        let mut squad_rcon = SquadRcon::connect(server.addr(), "password").unwrap();
+        squad_rcon.set_reconnect_policy(Some(
+            ReconnectPolicy::new()
+                .with_max_attempts(3)
+                .with_initial_backoff(Duration::from_millis(10)),
+        ));
+
+        drop(server);
+        assert!(squad_rcon.players().unwrap_err().is_connection_error());
+    }
+}
@@ -0,0 +1,89 @@
+use crate::{Player, Squad, Team};
+use serde::{Deserialize, Serialize};
+
+/// The state of a server at one point in time, as returned by
+/// `SquadRcon::snapshot`
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Snapshot {
+    players: Vec<Player>,
+    teams: Vec<Team>,
+    squads: Vec<Squad>,
+    current_map: String,
+    next_map: String,
+}
+
+impl Snapshot {
+    pub fn new(
+        players: Vec<Player>,
+        teams: Vec<Team>,
+        squads: Vec<Squad>,
+        current_map: String,
+        next_map: String,
+    ) -> Snapshot {
+        Snapshot {
+            players,
+            teams,
+            squads,
+            current_map,
+            next_map,
+        }
+    }
+
+    pub fn players(&self) -> &[Player] {
+        &self.players
+    }
+    pub fn teams(&self) -> &[Team] {
+        &self.teams
+    }
+    pub fn squads(&self) -> &[Squad] {
+        &self.squads
+    }
+    pub fn current_map(&self) -> &str {
+        &self.current_map
+    }
+    pub fn next_map(&self) -> &str {
+        &self.next_map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mock::fixtures::{many_players, server};
+    use crate::{RconConfig, SquadRcon, Terminator};
+    use std::time::Duration;
+
+    #[test]
+    fn snapshot() {
+        let server = server();
+        server.set_players(many_players(50));
+        server.set_fragment_size(Some(256));
+
+        for terminator in [
+            Terminator::EmptyResponse,
+            Terminator::ShowNextMap,
+            Terminator::Quiet(Duration::from_millis(100)),
+        ] {
+            let config = RconConfig::new().with_terminator(terminator);
+            let mut squad_rcon =
+                SquadRcon::connect_with_config(server.addr(), "password", config.clone()).unwrap();
+            let snapshot = squad_rcon.snapshot().unwrap();
+            assert_eq!(snapshot.players().len(), 50, "{:?}", terminator);
+            assert_eq!(snapshot.squads().len(), 2, "{:?}", terminator);
+            assert_eq!(snapshot.next_map(), "Gorodok_RAAS_v1", "{:?}", terminator);
+
+            let (mut squad_rcon, _) =
+                SquadRcon::connect_threaded_with_config(server.addr(), "password", config).unwrap();
+            let snapshot = squad_rcon.snapshot().unwrap();
+            assert_eq!(snapshot.players().len(), 50, "{:?}", terminator);
+            assert_eq!(snapshot.current_map(), "Narva_RAAS_v1", "{:?}", terminator);
+        }
+
+        // This server reports both maps in response to ShowNextMap
+        let commands = server.received_commands();
+        assert_eq!(
+            &commands[..3],
+            &["ListPlayers", "ListSquads", "ShowNextMap"]
+        );
+        assert!(!commands.contains(&"ShowCurrentMap".to_string()));
+    }
+}
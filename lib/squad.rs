@@ -7,19 +7,40 @@ pub struct Squad {
     size: usize,
     team_id: usize,
     locked: bool,
+    creator_name: Option<String>,
+    creator_steam_id: Option<String>,
+    creator_eos_id: Option<String>,
 }
 
 impl Squad {
     pub fn new(id: usize, name: String, size: usize, team_id: usize, locked: bool) -> Squad {
         Squad {
-            id: id,
-            name: name,
-            size: size,
-            team_id: team_id,
-            locked: locked,
+            id,
+            name,
+            size,
+            team_id,
+            locked,
+            creator_name: None,
+            creator_steam_id: None,
+            creator_eos_id: None,
         }
     }
 
+    pub fn with_creator_name<S: Into<String>>(mut self, creator_name: S) -> Squad {
+        self.creator_name = Some(creator_name.into());
+        self
+    }
+
+    pub fn with_creator_steam_id<S: Into<String>>(mut self, creator_steam_id: S) -> Squad {
+        self.creator_steam_id = Some(creator_steam_id.into());
+        self
+    }
+
+    pub fn with_creator_eos_id<S: Into<String>>(mut self, creator_eos_id: S) -> Squad {
+        self.creator_eos_id = Some(creator_eos_id.into());
+        self
+    }
+
     pub fn id(&self) -> usize {
         self.id
     }
@@ -35,4 +56,15 @@ impl Squad {
     pub fn locked(&self) -> bool {
         self.locked
     }
+    /// The name of the player who created the squad, on servers which list
+    /// it
+    pub fn creator_name(&self) -> Option<&str> {
+        self.creator_name.as_deref()
+    }
+    pub fn creator_steam_id(&self) -> Option<&str> {
+        self.creator_steam_id.as_deref()
+    }
+    pub fn creator_eos_id(&self) -> Option<&str> {
+        self.creator_eos_id.as_deref()
+    }
 }
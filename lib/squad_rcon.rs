@@ -1,126 +1,369 @@
+use crate::admin::Target;
 use crate::rcon::RconClient;
-use crate::{Chat, Error, Player, Squad, Team};
-use lazy_static::lazy_static;
-use regex::Regex;
+use crate::threaded_rcon::ThreadedRconClient;
+use crate::transport::{TcpTransport, Transport};
+use crate::{commands, parse, AdminActionResult, RconConfig, ReconnectPolicy, ServerEvent};
+use crate::{
+    Chat, Error, Layer, LayerInfo, MatchRule, Player, PlayerList, Preset, Snapshot, Squad, Team,
+};
 use std::net::ToSocketAddrs;
+use std::sync::mpsc::Receiver;
+use std::time::Duration;
 
 pub const SERVERDATA_CHAT: i32 = 1;
 
-lazy_static! {
-    static ref PLAYER_REGEX: Regex =
-        Regex::new(r"ID: (\d*) \| SteamID: (\d*) \| Name: (.*) \| Team ID: (.*) \| Squad ID: (.*)")
-            .expect("PLAYER_REGEX");
-    static ref SQUAD_REGEX: Regex =
-        Regex::new(r"ID: (\d*) \| Name: (.*) \| Size: (\d) \| Locked: (.*)").expect("SQUAD_REGEX");
-    static ref TEAM_REGEX: Regex = Regex::new(r"Team ID: (\d*) \((.*)\)").expect("TEAM_REGEX");
-    static ref MAPS_REGEX: Regex =
-        Regex::new(r"Current map is (.*), Next map is (.*)").expect("MAPS_REGEX");
-    static ref CHAT_REGEX: Regex =
-        Regex::new(r"\[(.*?)\] \[SteamID:(\d*)\] (.*?) : (.*)").expect("CHAT_REGEX");
+/// The underlying rcon connection of a `SquadRcon`
+enum Connection<T: Transport> {
+    /// Responses are read by the caller, and events are picked up along the way
+    Direct(RconClient<T>),
+    /// Responses and events are read by a dedicated reader thread
+    Threaded(ThreadedRconClient<T>),
 }
 
-/// A squad-specific rcon connection
-pub struct SquadRcon {
-    rcon_client: RconClient,
-    chat_log: Vec<String>,
+/// A squad-specific rcon connection.
+///
+/// Connections are made over TCP by default. Any other `Transport` can be
+/// used with `SquadRcon::from_transport`.
+pub struct SquadRcon<T: Transport = TcpTransport> {
+    connection: Connection<T>,
+    event_log: Vec<String>,
+    reconnect_policy: Option<ReconnectPolicy>,
+    /// Overrides the configured command timeout, while set
+    command_timeout: Option<Option<Duration>>,
+    /// How the server reports layers, once it is known
+    layer_format: Option<LayerFormat>,
+    /// Counts every command sent, so `SquadRconHandle` can rate limit them
+    commands_sent: u64,
 }
 
-impl SquadRcon {
+/// How a server reports the current and next layer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum LayerFormat {
+    /// Older servers report both maps in response to `ShowNextMap`, and may
+    /// not answer `ShowCurrentMap` at all
+    Maps,
+    /// Newer servers report one layer each in response to `ShowCurrentMap`
+    /// and `ShowNextMap`
+    Layers,
+}
+
+impl LayerFormat {
+    /// Recognise the format from a response to `ShowNextMap`
+    pub(crate) fn detect(next_map_string: &str) -> LayerFormat {
+        if parse::reports_both_maps(next_map_string) {
+            LayerFormat::Maps
+        } else {
+            LayerFormat::Layers
+        }
+    }
+
+    /// The commands which report the current and next layer. Until the
+    /// format is known, only `ShowNextMap` is sent, as every server answers
+    /// it.
+    pub(crate) fn commands(layer_format: Option<LayerFormat>) -> &'static [&'static str] {
+        match layer_format {
+            Some(LayerFormat::Layers) => &[commands::SHOW_CURRENT_MAP, commands::SHOW_NEXT_MAP],
+            _ => &[commands::SHOW_NEXT_MAP],
+        }
+    }
+}
+
+impl SquadRcon<TcpTransport> {
     pub fn connect<A: ToSocketAddrs, S: Into<String>>(
         addr: A,
         password: S,
+    ) -> Result<SquadRcon, Error> {
+        SquadRcon::connect_with_config(addr, password, RconConfig::default())
+    }
+
+    /// Connect with the given timeouts and settings
+    pub fn connect_with_config<A: ToSocketAddrs, S: Into<String>>(
+        addr: A,
+        password: S,
+        config: RconConfig,
     ) -> Result<SquadRcon, Error> {
         Ok(SquadRcon {
-            rcon_client: RconClient::connect(addr, password)?,
-            chat_log: Vec::new(),
+            connection: Connection::Direct(RconClient::connect_with_config(
+                addr, password, config,
+            )?),
+            event_log: Vec::new(),
+            reconnect_policy: None,
+            command_timeout: None,
+            layer_format: None,
+            commands_sent: 0,
         })
     }
 
-    pub fn reconnect(&mut self) -> Result<(), Error> {
-        self.rcon_client.reconnect()
+    /// Connect with a dedicated thread reading from the server.
+    ///
+    /// Chat messages and other events are delivered to the returned `Receiver`
+    /// as soon as they arrive, rather than being collected for `take_events`.
+    ///
+    /// The `Receiver` keeps receiving events after `reconnect`, so it is not
+    /// closed when the connection is lost, only once the `SquadRcon` is
+    /// dropped. A lost connection is reported by the next command instead.
+    pub fn connect_threaded<A: ToSocketAddrs, S: Into<String>>(
+        addr: A,
+        password: S,
+    ) -> Result<(SquadRcon, Receiver<ServerEvent>), Error> {
+        SquadRcon::connect_threaded_with_config(addr, password, RconConfig::default())
     }
 
-    /// Take the current chat log. This resets the chat log.
-    pub fn take_chat_log(&mut self) -> Result<Vec<Chat>, Error> {
-        let chat_log = std::mem::replace(&mut self.chat_log, Vec::new());
-
-        let mut chats = Vec::new();
-        for line in chat_log {
-            let captures = CHAT_REGEX.captures(&line).ok_or(Error::SquadParsingError)?;
-
-            chats.push(Chat::new(
-                captures.get(1).unwrap().as_str().to_string(),
-                captures.get(2).unwrap().as_str().to_string(),
-                captures.get(3).unwrap().as_str().to_string(),
-                captures.get(4).unwrap().as_str().to_string(),
-            ));
+    /// Connect with a dedicated thread reading from the server, with the
+    /// given timeouts and settings
+    pub fn connect_threaded_with_config<A: ToSocketAddrs, S: Into<String>>(
+        addr: A,
+        password: S,
+        config: RconConfig,
+    ) -> Result<(SquadRcon, Receiver<ServerEvent>), Error> {
+        let (threaded_rcon_client, event_receiver) =
+            ThreadedRconClient::connect(addr, password, config)?;
+        let squad_rcon = SquadRcon {
+            connection: Connection::Threaded(threaded_rcon_client),
+            event_log: Vec::new(),
+            reconnect_policy: None,
+            command_timeout: None,
+            layer_format: None,
+            commands_sent: 0,
+        };
+        Ok((squad_rcon, event_receiver))
+    }
+
+    /// Connect to `host`, such as `"example.com:21114"`, with the given
+    /// timeouts and settings. The host is resolved again each time the
+    /// connection is re-established, picking up DNS changes.
+    pub fn connect_host<H: Into<String>, S: Into<String>>(
+        host: H,
+        password: S,
+        config: RconConfig,
+    ) -> Result<SquadRcon, Error> {
+        let transport = TcpTransport::connect_host(host, config.connect_timeout())?;
+        SquadRcon::from_transport(transport, password, config)
+    }
+
+    /// Connect to `host` with a dedicated thread reading from the server, as
+    /// with `SquadRcon::connect_threaded`. The host is resolved again each
+    /// time the connection is re-established.
+    pub fn connect_host_threaded<H: Into<String>, S: Into<String>>(
+        host: H,
+        password: S,
+        config: RconConfig,
+    ) -> Result<(SquadRcon, Receiver<ServerEvent>), Error> {
+        let transport = TcpTransport::connect_host(host, config.connect_timeout())?;
+        SquadRcon::from_transport_threaded(transport, password, config)
+    }
+}
+
+impl<T: Transport> SquadRcon<T> {
+    /// Authenticate over an already established connection
+    pub fn from_transport<S: Into<String>>(
+        transport: T,
+        password: S,
+        config: RconConfig,
+    ) -> Result<SquadRcon<T>, Error> {
+        Ok(SquadRcon {
+            connection: Connection::Direct(RconClient::from_transport(
+                transport, password, config,
+            )?),
+            event_log: Vec::new(),
+            reconnect_policy: None,
+            command_timeout: None,
+            layer_format: None,
+            commands_sent: 0,
+        })
+    }
+
+    /// Authenticate over an already established connection, with a dedicated
+    /// thread reading from the server
+    pub fn from_transport_threaded<S: Into<String>>(
+        transport: T,
+        password: S,
+        config: RconConfig,
+    ) -> Result<(SquadRcon<T>, Receiver<ServerEvent>), Error> {
+        let (threaded_rcon_client, event_receiver) = ThreadedRconClient::from_rcon_client(
+            RconClient::from_transport(transport, password, config)?,
+        )?;
+        let squad_rcon = SquadRcon {
+            connection: Connection::Threaded(threaded_rcon_client),
+            event_log: Vec::new(),
+            reconnect_policy: None,
+            command_timeout: None,
+            layer_format: None,
+            commands_sent: 0,
+        };
+        Ok((squad_rcon, event_receiver))
+    }
+
+    pub fn reconnect(&mut self) -> Result<(), Error> {
+        // The server may have been updated while it was away
+        self.layer_format = None;
+        match &mut self.connection {
+            Connection::Direct(rcon_client) => rcon_client.reconnect(),
+            Connection::Threaded(threaded_rcon_client) => threaded_rcon_client.reconnect(),
         }
+    }
+
+    /// Set how this connection reconnects when the server goes away, or
+    /// `None` to never reconnect automatically. This is `None` by default.
+    ///
+    /// With a policy set, queries such as `players` and `squads` reconnect,
+    /// re-authenticate, and retry transparently. Commands which change server
+    /// state, such as `ban`, `change_map`, and `raw_command`, reconnect but
+    /// are not retried, as they may already have been executed. They return
+    /// the original error.
+    pub fn set_reconnect_policy(&mut self, reconnect_policy: Option<ReconnectPolicy>) {
+        self.reconnect_policy = reconnect_policy;
+    }
 
-        Ok(chats)
+    pub fn reconnect_policy(&self) -> Option<&ReconnectPolicy> {
+        self.reconnect_policy.as_ref()
+    }
+
+    /// Run `f` with every command it sends given `timeout` to be answered,
+    /// instead of the configured command timeout.
+    ///
+    /// Commands which are not answered in time fail with
+    /// `Error::CommandTimeout`, and the connection remains usable.
+    ///
+    /// ```no_run
+    /// # use squad_rcon::SquadRcon;
+    /// # use std::time::Duration;
+    /// let mut squad_rcon = SquadRcon::connect("127.0.0.1:21114", "password").unwrap();
+    /// let players = squad_rcon.with_command_timeout(Some(Duration::from_secs(2)), |squad_rcon| {
+    ///     squad_rcon.players()
+    /// });
+    /// ```
+    pub fn with_command_timeout<F, R>(&mut self, timeout: Option<Duration>, f: F) -> R
+    where
+        F: FnOnce(&mut SquadRcon<T>) -> R,
+    {
+        let previous = self.command_timeout.replace(timeout);
+        let result = f(self);
+        self.command_timeout = previous;
+        result
+    }
+
+    /// Take every event received from the server so far. This resets the
+    /// event log.
+    ///
+    /// This is always empty for connections made with
+    /// `SquadRcon::connect_threaded`.
+    pub fn take_events(&mut self) -> Vec<ServerEvent> {
+        std::mem::take(&mut self.event_log)
+            .iter()
+            .map(|body| ServerEvent::parse(body))
+            .collect()
+    }
+
+    /// Take the chat messages received from the server so far. This resets
+    /// the event log, discarding any events which are not chat messages.
+    ///
+    /// This is always empty for connections made with
+    /// `SquadRcon::connect_threaded`.
+    pub fn take_chat_log(&mut self) -> Result<Vec<Chat>, Error> {
+        Ok(self
+            .take_events()
+            .into_iter()
+            .filter_map(|event| match event {
+                ServerEvent::Chat(chat) => Some(chat),
+                _ => None,
+            })
+            .collect())
     }
 
     /// Execute a raw rcon command, and return the result.
     ///
-    /// This is a convenience wrapper around `RconClient::exec_command`.
+    /// This is a convenience wrapper around `RconClient::exec_command2`.
     pub fn raw_command<S: Into<String>>(&mut self, command: S) -> Result<String, Error> {
-        let (response, other_packets) = self.rcon_client.exec_command2(command)?;
+        let result = self.exec_command(command.into());
+        if let Err(e) = &result {
+            // Leave the connection usable for the next command, but report
+            // the original error
+            self.reconnect_after(e).ok();
+        }
+        result
+    }
 
-        for packet in other_packets {
-            if packet.type_() == SERVERDATA_CHAT {
-                self.chat_log.push(packet.body().to_string());
+    /// Execute an admin command, and parse the server's response
+    fn admin_command<S: Into<String>>(
+        &mut self,
+        target: Target,
+        command: S,
+    ) -> Result<AdminActionResult, Error> {
+        AdminActionResult::parse_for(target, &self.raw_command(command)?)
+    }
+
+    /// Execute a command which does not change server state, retrying it
+    /// once after reconnecting if the connection is lost.
+    fn query(&mut self, command: &str) -> Result<String, Error> {
+        Ok(self.query_all(&[command])?.remove(0))
+    }
+
+    /// Execute several commands which do not change server state at once,
+    /// retrying them once after reconnecting if the connection is lost.
+    fn query_all(&mut self, commands: &[&str]) -> Result<Vec<String>, Error> {
+        match self.exec_commands(commands) {
+            Err(e) if e.is_connection_error() && self.reconnect_policy.is_some() => {
+                self.reconnect_after(&e)?;
+                self.exec_commands(commands)
             }
+            result => result,
         }
-
-        Ok(response)
     }
 
-    /// Return all of the players on the squad server
-    pub fn players(&mut self) -> Result<Vec<Player>, Error> {
-        let players_string = self.raw_command("ListPlayers")?;
+    /// Reconnect per the reconnect policy, if there is one and `error` means
+    /// the connection was lost
+    fn reconnect_after(&mut self, error: &Error) -> Result<(), Error> {
+        let reconnect_policy = match &self.reconnect_policy {
+            Some(reconnect_policy) if error.is_connection_error() => reconnect_policy.clone(),
+            _ => return Ok(()),
+        };
+        reconnect_policy.retry(|| self.reconnect())
+    }
 
-        let mut lines = players_string
-            .split("\n")
-            .into_iter()
-            .map(|s| s.to_string())
-            .collect::<Vec<String>>();
-        lines.remove(0);
+    fn exec_command(&mut self, command: String) -> Result<String, Error> {
+        Ok(self.exec_commands(&[command])?.remove(0))
+    }
 
-        let mut players = Vec::new();
+    /// The number of commands sent on this connection so far
+    pub(crate) fn commands_sent(&self) -> u64 {
+        self.commands_sent
+    }
 
-        for line in lines {
-            if line.contains("Recently Disconnected Players") {
-                break;
+    fn exec_commands<S: AsRef<str>>(&mut self, commands: &[S]) -> Result<Vec<String>, Error> {
+        self.commands_sent += commands.len() as u64;
+        let command_timeout = self.command_timeout;
+        let rcon_client = match &mut self.connection {
+            Connection::Direct(rcon_client) => rcon_client,
+            Connection::Threaded(threaded_rcon_client) => {
+                let timeout = command_timeout
+                    .unwrap_or_else(|| threaded_rcon_client.config().command_timeout());
+                return threaded_rcon_client.exec_commands(commands, timeout);
             }
+        };
 
-            let captures = PLAYER_REGEX
-                .captures(&line)
-                .ok_or(Error::SquadParsingError)?;
-
-            let team_id = captures
-                .get(4)
-                .expect("players get 4")
-                .as_str()
-                .parse()
-                .ok();
-            let squad_id = captures
-                .get(5)
-                .expect("players get 5")
-                .as_str()
-                .parse()
-                .ok();
-
-            let player = Player::new(
-                captures.get(1).expect("players get 1").as_str().parse()?,
-                captures.get(2).expect("players get 2").as_str().to_string(),
-                captures.get(3).expect("players get 3").as_str().to_string(),
-                team_id,
-                squad_id,
-            );
-
-            players.push(player);
+        let timeout = command_timeout.unwrap_or_else(|| rcon_client.config().command_timeout());
+        let (responses, other_packets) =
+            rcon_client.exec_commands_with_timeout(commands, timeout)?;
+
+        for packet in other_packets {
+            if packet.type_() == SERVERDATA_CHAT {
+                self.event_log.push(packet.body().to_string());
+            }
         }
 
-        Ok(players)
+        Ok(responses)
+    }
+
+    /// Return all of the players on the squad server
+    pub fn players(&mut self) -> Result<Vec<Player>, Error> {
+        parse::players(&self.query(commands::LIST_PLAYERS)?)
+    }
+
+    /// Return the players on the squad server, and the players who left it
+    /// recently
+    pub fn player_list(&mut self) -> Result<PlayerList, Error> {
+        parse::player_list(&self.query(commands::LIST_PLAYERS)?)
     }
 
     /// Return all of the teams and squads on the server.
@@ -128,153 +371,270 @@ impl SquadRcon {
     /// These are returned in one call by the server as per the squad rcon
     /// protocol. This is one underlying rcon command.
     pub fn squads(&mut self) -> Result<(Vec<Team>, Vec<Squad>), Error> {
-        let squads_string = self.raw_command("ListSquads")?;
-
-        let mut lines = squads_string
-            .split("\n")
-            .into_iter()
-            .map(|s| s.to_string())
-            .collect::<Vec<String>>();
-        lines.remove(0);
-
-        let mut teams = Vec::new();
-        let mut squads = Vec::new();
-
-        let mut current_team = 0;
-
-        for line in lines {
-            if let Some(captures) = TEAM_REGEX.captures(&line) {
-                let id = captures
-                    .get(1)
-                    .expect("squads team get 1")
-                    .as_str()
-                    .parse()
-                    .unwrap_or(0);
-                let name = captures
-                    .get(2)
-                    .expect("squads team get 2")
-                    .as_str()
-                    .to_string();
-
-                current_team = id;
-                let team = Team::new(id, name);
-                teams.push(team);
-            } else if let Some(captures) = SQUAD_REGEX.captures(&line) {
-                let id = captures
-                    .get(1)
-                    .expect("squads get 1")
-                    .as_str()
-                    .parse()
-                    .unwrap_or(0);
-                let name = captures.get(2).expect("squads get 2").as_str().to_string();
-                let size = captures
-                    .get(3)
-                    .expect("squads get 3")
-                    .as_str()
-                    .parse()
-                    .unwrap_or(0);
-                let locked = captures.get(4).expect("squads get 4").as_str() == "True";
-                let squad = Squad::new(id, name, size, current_team, locked);
-
-                squads.push(squad);
-            } else {
-                return Err(Error::SquadParsingError);
-            }
-        }
-
-        Ok((teams, squads))
+        parse::squads(&self.query(commands::LIST_SQUADS)?)
     }
 
     /// Return a list of all the maps supported by the server
     pub fn list_maps(&mut self) -> Result<Vec<String>, Error> {
-        let maps_string = self.raw_command("ListMaps")?;
+        Ok(parse::list_maps(&self.query(commands::LIST_MAPS)?))
+    }
 
-        Ok(maps_string
-            .split("\n")
-            .into_iter()
-            .map(|s| s.to_string())
-            .collect::<Vec<String>>())
+    /// Return a list of all the maps supported by the server, as layers.
+    /// Names which are not recognised as layers are left out.
+    pub fn list_maps_as_layers(&mut self) -> Result<Vec<Layer>, Error> {
+        Ok(parse::list_layers(&self.query(commands::LIST_MAPS)?))
+    }
+
+    /// Return a list of all the layers supported by the server
+    pub fn list_layers(&mut self) -> Result<Vec<String>, Error> {
+        Ok(parse::list_maps(&self.query(commands::LIST_LAYERS)?))
     }
 
     /// End the current match
-    pub fn end_match(&mut self) -> Result<String, Error> {
-        self.raw_command("AdminEndMatch")
+    pub fn end_match(&mut self) -> Result<AdminActionResult, Error> {
+        self.admin_command(Target::Server, commands::END_MATCH)
+    }
+
+    /// Restart the current match
+    pub fn restart_match(&mut self) -> Result<AdminActionResult, Error> {
+        self.admin_command(Target::Server, commands::RESTART_MATCH)
+    }
+
+    /// Set the number of player slots, between 1 and 100
+    pub fn set_max_num_players(&mut self, players: usize) -> Result<AdminActionResult, Error> {
+        self.admin_command(Target::Server, commands::set_max_num_players(players)?)
+    }
+
+    /// Set how many of the player slots are reserved
+    pub fn set_num_reserved_slots(&mut self, slots: usize) -> Result<AdminActionResult, Error> {
+        self.admin_command(Target::Server, commands::set_num_reserved_slots(slots)?)
+    }
+
+    /// Set the password needed to join the server. An empty password removes
+    /// it.
+    pub fn set_server_password<S: AsRef<str>>(
+        &mut self,
+        password: S,
+    ) -> Result<AdminActionResult, Error> {
+        self.admin_command(
+            Target::Server,
+            commands::set_server_password(password.as_ref())?,
+        )
+    }
+
+    /// Set how many players can wait in the public queue. `-1` means no limit,
+    /// and `0` disables the queue.
+    pub fn set_public_queue_limit(&mut self, limit: i32) -> Result<AdminActionResult, Error> {
+        self.admin_command(Target::Server, commands::set_public_queue_limit(limit)?)
+    }
+
+    /// Set the speed of the game clock, where `1.0` is normal speed
+    pub fn slomo(&mut self, scale: f32) -> Result<AdminActionResult, Error> {
+        self.admin_command(Target::Server, commands::slomo(scale)?)
+    }
+
+    /// Switch a match rule on or off
+    pub fn set_match_rule(
+        &mut self,
+        rule: MatchRule,
+        enabled: bool,
+    ) -> Result<AdminActionResult, Error> {
+        self.admin_command(Target::Server, commands::match_rule(rule, enabled)?)
+    }
+
+    /// Make every role available, regardless of squad size or team limits
+    pub fn force_all_role_availability(
+        &mut self,
+        enabled: bool,
+    ) -> Result<AdminActionResult, Error> {
+        self.set_match_rule(MatchRule::ForceAllRoleAvailability, enabled)
+    }
+
+    /// Make every vehicle available, regardless of team limits
+    pub fn force_all_vehicle_availability(
+        &mut self,
+        enabled: bool,
+    ) -> Result<AdminActionResult, Error> {
+        self.set_match_rule(MatchRule::ForceAllVehicleAvailability, enabled)
+    }
+
+    /// Allow every deployable to be built, regardless of team limits
+    pub fn force_all_deployable_availability(
+        &mut self,
+        enabled: bool,
+    ) -> Result<AdminActionResult, Error> {
+        self.set_match_rule(MatchRule::ForceAllDeployableAvailability, enabled)
+    }
+
+    /// Let players respawn without waiting
+    pub fn no_respawn_timer(&mut self, enabled: bool) -> Result<AdminActionResult, Error> {
+        self.set_match_rule(MatchRule::NoRespawnTimer, enabled)
+    }
+
+    /// Let players change teams without waiting
+    pub fn no_team_change_timer(&mut self, enabled: bool) -> Result<AdminActionResult, Error> {
+        self.set_match_rule(MatchRule::NoTeamChangeTimer, enabled)
+    }
+
+    /// Let vehicles be used without being claimed by a squad
+    pub fn disable_vehicle_claiming(&mut self, enabled: bool) -> Result<AdminActionResult, Error> {
+        self.set_match_rule(MatchRule::DisableVehicleClaiming, enabled)
+    }
+
+    /// Set every rule of a preset, stopping at the first rule the server
+    /// rejects
+    pub fn apply_preset(&mut self, preset: &Preset) -> Result<Vec<AdminActionResult>, Error> {
+        let mut results = Vec::new();
+        for command in preset.commands()? {
+            results.push(self.admin_command(Target::Server, command)?);
+        }
+        Ok(results)
+    }
+
+    /// Set every rule of a preset back to the default, off
+    pub fn revert_preset(&mut self, preset: &Preset) -> Result<Vec<AdminActionResult>, Error> {
+        self.apply_preset(&preset.reverted())
     }
 
     /// Change the map currently running on the squad server.
-    pub fn change_map<S: AsRef<str>>(&mut self, map: S) -> Result<String, Error> {
-        self.raw_command(format!("AdminChangeMap {}", map.as_ref()))
+    pub fn change_map<S: AsRef<str>>(&mut self, map: S) -> Result<AdminActionResult, Error> {
+        self.admin_command(Target::Layer, commands::change_map(map.as_ref())?)
+    }
+
+    /// Change the layer currently running on the squad server.
+    pub fn change_layer<S: AsRef<str>>(&mut self, layer: S) -> Result<AdminActionResult, Error> {
+        self.admin_command(Target::Layer, commands::change_layer(layer.as_ref())?)
+    }
+
+    /// Set the layer which will run on the squad server when the current game
+    /// is finished.
+    pub fn set_next_layer<S: AsRef<str>>(&mut self, layer: S) -> Result<AdminActionResult, Error> {
+        self.admin_command(Target::Layer, commands::set_next_layer(layer.as_ref())?)
     }
 
     /// Set the map which will run on the squad server when the current game is
     /// finished.
-    pub fn set_next_map<S: AsRef<str>>(&mut self, map: S) -> Result<String, Error> {
-        self.raw_command(format!("AdminSetNextMap {}", map.as_ref()))
+    pub fn set_next_map<S: AsRef<str>>(&mut self, map: S) -> Result<AdminActionResult, Error> {
+        self.admin_command(Target::Layer, commands::set_next_map(map.as_ref())?)
     }
 
     /// Force a player onto the other team.
     ///
     /// `name` can be player name or steam64id.
-    pub fn force_team_change<S: AsRef<str>>(&mut self, name: S) -> Result<String, Error> {
-        self.raw_command(format!("AdminForceTeamChange {}", name.as_ref()))
+    pub fn force_team_change<S: AsRef<str>>(
+        &mut self,
+        name: S,
+    ) -> Result<AdminActionResult, Error> {
+        self.admin_command(Target::Player, commands::force_team_change(name.as_ref())?)
     }
 
     /// Demote the commander.
     ///
     /// `name` can be player name or steam64id.
-    pub fn demote_commander<S: AsRef<str>>(&mut self, name: S) -> Result<String, Error> {
-        self.raw_command(format!("AdminDemoteCommander {}", name.as_ref()))
+    pub fn demote_commander<S: AsRef<str>>(&mut self, name: S) -> Result<AdminActionResult, Error> {
+        self.admin_command(Target::Player, commands::demote_commander(name.as_ref())?)
     }
 
     /// Disband a squad.
-    pub fn disband_squad(&mut self, team_id: usize, squad_id: usize) -> Result<String, Error> {
-        self.raw_command(format!("AdminDisbandSquad {} {}", team_id, squad_id))
+    pub fn disband_squad(
+        &mut self,
+        team_id: usize,
+        squad_id: usize,
+    ) -> Result<AdminActionResult, Error> {
+        self.admin_command(Target::Server, commands::disband_squad(team_id, squad_id)?)
+    }
+
+    /// Disband the given squad, using its team id and squad id
+    pub fn disband(&mut self, squad: &Squad) -> Result<AdminActionResult, Error> {
+        self.disband_squad(squad.team_id(), squad.id())
+    }
+
+    /// Reset a squad's name to the default, such as "Squad 1"
+    pub fn rename_squad(
+        &mut self,
+        team_id: usize,
+        squad_id: usize,
+    ) -> Result<AdminActionResult, Error> {
+        self.admin_command(Target::Server, commands::rename_squad(team_id, squad_id)?)
+    }
+
+    /// Reset the given squad's name to the default
+    pub fn reset_squad_name(&mut self, squad: &Squad) -> Result<AdminActionResult, Error> {
+        self.rename_squad(squad.team_id(), squad.id())
+    }
+
+    /// Reset the name of every squad for which `allowed` returns false, such
+    /// as squads named against server rules.
+    ///
+    /// Returns the squads which were renamed, with their old names.
+    pub fn enforce_squad_names<F>(&mut self, allowed: F) -> Result<Vec<Squad>, Error>
+    where
+        F: Fn(&Squad) -> bool,
+    {
+        let (_, squads) = self.squads()?;
+        let mut renamed = Vec::new();
+        for squad in squads.into_iter().filter(|squad| !allowed(squad)) {
+            self.reset_squad_name(&squad)?;
+            renamed.push(squad);
+        }
+        Ok(renamed)
+    }
+
+    /// Remove a player from their squad.
+    ///
+    /// `name` can be player name or steam64id.
+    pub fn remove_player_from_squad<S: AsRef<str>>(
+        &mut self,
+        name: S,
+    ) -> Result<AdminActionResult, Error> {
+        self.admin_command(
+            Target::Player,
+            commands::remove_player_from_squad(name.as_ref())?,
+        )
     }
 
     /// Broadcast an administrative message to the server.
-    pub fn broadcast<M>(&mut self, message: M) -> Result<String, Error>
+    pub fn broadcast<M>(&mut self, message: M) -> Result<AdminActionResult, Error>
     where
         M: AsRef<str>,
     {
-        self.raw_command(format!("AdminBroadcast {}", message.as_ref()))
+        self.admin_command(Target::Message, commands::broadcast(message.as_ref())?)
     }
 
     /// Send a message to admin chat, which only admins can see.
-    pub fn chat_to_admin<M>(&mut self, message: M) -> Result<String, Error>
+    pub fn chat_to_admin<M>(&mut self, message: M) -> Result<AdminActionResult, Error>
     where
         M: AsRef<str>,
     {
-        self.raw_command(format!("ChatToAdmin {}", message.as_ref()))
+        self.admin_command(Target::Message, commands::chat_to_admin(message.as_ref())?)
     }
 
     /// Warn a player by name or steamid
     ///
     /// `name` can be the player's name, or their steam64id
-    pub fn warn<N, R>(&mut self, name: N, reason: R) -> Result<String, Error>
+    pub fn warn<N, R>(&mut self, name: N, reason: R) -> Result<AdminActionResult, Error>
     where
         N: AsRef<str>,
         R: AsRef<str>,
     {
-        self.raw_command(format!(
-            "AdminWarn \"{}\" {}",
-            name.as_ref(),
-            reason.as_ref()
-        ))
+        self.admin_command(
+            Target::Player,
+            commands::warn(name.as_ref(), reason.as_ref())?,
+        )
     }
 
     /// Kick a player by name or steamid
     ///
     /// `name` can be the player's name, or their steam64id
-    pub fn kick<N, R>(&mut self, name: N, reason: R) -> Result<String, Error>
+    pub fn kick<N, R>(&mut self, name: N, reason: R) -> Result<AdminActionResult, Error>
     where
         N: AsRef<str>,
         R: AsRef<str>,
     {
-        self.raw_command(format!(
-            "AdminKick \"{}\" {}",
-            name.as_ref(),
-            reason.as_ref()
-        ))
+        self.admin_command(
+            Target::Player,
+            commands::kick(name.as_ref(), reason.as_ref())?,
+        )
     }
 
     /// Ban a user for a diven amount of time
@@ -284,33 +644,449 @@ impl SquadRcon {
     /// * 0 = permanent ban
     ///
     /// `name` can be the player's name, or their steam64id
-    pub fn ban<N, L, R>(&mut self, name: N, length: L, reason: R) -> Result<String, Error>
+    pub fn ban<N, L, R>(
+        &mut self,
+        name: N,
+        length: L,
+        reason: R,
+    ) -> Result<AdminActionResult, Error>
     where
         N: AsRef<str>,
         L: AsRef<str>,
         R: AsRef<str>,
     {
-        self.raw_command(format!(
-            "AdminBan \"{}\" \"{}\" {}",
-            name.as_ref(),
-            length.as_ref(),
-            reason.as_ref()
-        ))
+        self.admin_command(
+            Target::Player,
+            commands::ban(name.as_ref(), length.as_ref(), reason.as_ref())?,
+        )
+    }
+
+    /// Warn a player by their id, or a `&Player`
+    pub fn warn_by_id<I, R>(&mut self, id: I, reason: R) -> Result<AdminActionResult, Error>
+    where
+        I: Into<usize>,
+        R: AsRef<str>,
+    {
+        self.admin_command(
+            Target::Player,
+            commands::warn_by_id(id.into(), reason.as_ref())?,
+        )
+    }
+
+    /// Kick a player by their id, or a `&Player`
+    pub fn kick_by_id<I, R>(&mut self, id: I, reason: R) -> Result<AdminActionResult, Error>
+    where
+        I: Into<usize>,
+        R: AsRef<str>,
+    {
+        self.admin_command(
+            Target::Player,
+            commands::kick_by_id(id.into(), reason.as_ref())?,
+        )
+    }
+
+    /// Ban a player by their id, or a `&Player`. `length` is as for `ban`.
+    pub fn ban_by_id<I, L, R>(
+        &mut self,
+        id: I,
+        length: L,
+        reason: R,
+    ) -> Result<AdminActionResult, Error>
+    where
+        I: Into<usize>,
+        L: AsRef<str>,
+        R: AsRef<str>,
+    {
+        self.admin_command(
+            Target::Player,
+            commands::ban_by_id(id.into(), length.as_ref(), reason.as_ref())?,
+        )
+    }
+
+    /// Move a player to the other team by their id, or a `&Player`
+    pub fn force_team_change_by_id<I: Into<usize>>(
+        &mut self,
+        id: I,
+    ) -> Result<AdminActionResult, Error> {
+        self.admin_command(
+            Target::Player,
+            commands::force_team_change_by_id(id.into())?,
+        )
+    }
+
+    /// Remove a player from their squad by their id, or a `&Player`
+    pub fn remove_player_from_squad_by_id<I: Into<usize>>(
+        &mut self,
+        id: I,
+    ) -> Result<AdminActionResult, Error> {
+        self.admin_command(
+            Target::Player,
+            commands::remove_player_from_squad_by_id(id.into())?,
+        )
     }
 
     /// Get the current map, and the next map
     ///
-    /// Result is (current_map, next_map)
+    /// Result is (current_map, next_map). `next_map` is empty if no next map
+    /// is set.
     pub fn maps(&mut self) -> Result<(String, String), Error> {
-        let maps_string = self.raw_command("ShowNextMap")?;
+        let (current, next) = self.layers()?;
+        Ok(maps(current, next))
+    }
+
+    /// Get the current layer, and the next layer if one is set, parsed into
+    /// `Layer`s
+    pub fn maps_as_layers(&mut self) -> Result<(Layer, Option<Layer>), Error> {
+        let (current, next) = self.layers()?;
+        parse::parsed_layers(&current, next.as_ref())
+    }
+
+    /// Get the current layer, and the next layer if one is set.
+    ///
+    /// The first call learns how the server reports layers, which takes an
+    /// extra round trip on newer servers. Later calls take one.
+    pub fn layers(&mut self) -> Result<(LayerInfo, Option<LayerInfo>), Error> {
+        let responses = self.query_all(LayerFormat::commands(self.layer_format))?;
+        self.parse_layers(&responses)
+    }
+
+    /// Parse the responses to `LayerFormat::commands`, learning the layer
+    /// format from them if it is not yet known
+    fn parse_layers(
+        &mut self,
+        responses: &[String],
+    ) -> Result<(LayerInfo, Option<LayerInfo>), Error> {
+        match responses {
+            [current, next] => parse::layers(current, next),
+            [next] => {
+                let layer_format = LayerFormat::detect(next);
+                self.layer_format = Some(layer_format);
+                match layer_format {
+                    LayerFormat::Maps => parse::layers("", next),
+                    LayerFormat::Layers => {
+                        let current = self.query(commands::SHOW_CURRENT_MAP)?;
+                        parse::layers(&current, next)
+                    }
+                }
+            }
+            _ => Err(Error::ProtocolError),
+        }
+    }
+
+    /// Return the players, teams, squads and maps on the server.
+    ///
+    /// The commands behind `players`, `squads` and `maps` are sent at once,
+    /// so this takes about as long as any one of them.
+    pub fn snapshot(&mut self) -> Result<Snapshot, Error> {
+        let mut commands = vec![commands::LIST_PLAYERS, commands::LIST_SQUADS];
+        commands.extend(LayerFormat::commands(self.layer_format));
+        let responses = self.query_all(&commands)?;
+        let players = parse::players(&responses[0])?;
+        let (teams, squads) = parse::squads(&responses[1])?;
+        let (current, next) = self.parse_layers(&responses[2..])?;
+        let (current_map, next_map) = maps(current, next);
+        Ok(Snapshot::new(players, teams, squads, current_map, next_map))
+    }
+}
+
+/// The layer names of `(current_layer, next_layer)`, as returned by `maps`
+pub(crate) fn maps(current: LayerInfo, next: Option<LayerInfo>) -> (String, String) {
+    (
+        current.layer().to_string(),
+        next.map(|next| next.layer().to_string())
+            .unwrap_or_default(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mock::fixtures::{exact_config, many_players, server, PLAYERS_WITH_DISCONNECTED};
+    use crate::{Error, GameMode, RconConfig, SquadRcon, Terminator};
+    use std::time::Duration;
+
+    #[test]
+    fn authentication_failure() {
+        let server = server();
+        match SquadRcon::connect(server.addr(), "wrong") {
+            Err(Error::AuthenticationFailure) => {}
+            other => panic!("expected AuthenticationFailure, got {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn players() {
+        let server = server();
+        let mut squad_rcon = SquadRcon::connect(server.addr(), "password").unwrap();
+
+        let players = squad_rcon.players().unwrap();
+        assert_eq!(players.len(), 2);
+        assert_eq!(players[0].name(), "Alice");
+        assert_eq!(players[0].steam_id(), "76561198000000001");
+        assert_eq!(players[0].squad_id(), Some(1));
+        assert_eq!(players[1].team_id(), Some(2));
+        assert_eq!(players[1].squad_id(), None);
+        assert!(squad_rcon.player_list().unwrap().disconnected().is_empty());
+    }
+
+    #[test]
+    fn player_list() {
+        let server = server();
+        server.set_players(PLAYERS_WITH_DISCONNECTED);
+        let mut squad_rcon = SquadRcon::connect(server.addr(), "password").unwrap();
+
+        assert_eq!(squad_rcon.players().unwrap().len(), 2);
+        let player_list = squad_rcon.player_list().unwrap();
+        assert_eq!(player_list.players().len(), 2);
+        assert_eq!(player_list.disconnected()[0].name(), "Carol");
+    }
+
+    #[test]
+    fn squads() {
+        let server = server();
+        let mut squad_rcon = SquadRcon::connect(server.addr(), "password").unwrap();
+
+        let (teams, squads) = squad_rcon.squads().unwrap();
+        assert_eq!(teams.len(), 2);
+        assert_eq!(teams[1].name(), "Russian Ground Forces");
+        assert_eq!(squads.len(), 2);
+        assert_eq!(squads[1].team_id(), 2);
+        assert!(squads[1].locked());
+    }
+
+    #[test]
+    fn maps() {
+        let server = server();
+        let mut squad_rcon = SquadRcon::connect(server.addr(), "password").unwrap();
+
+        let (current_map, next_map) = squad_rcon.maps().unwrap();
+        assert_eq!(current_map, "Narva_RAAS_v1");
+        assert_eq!(next_map, "Gorodok_RAAS_v1");
+        assert_eq!(
+            squad_rcon.list_maps().unwrap(),
+            vec!["Narva_RAAS_v1", "Gorodok_RAAS_v1"]
+        );
+    }
+
+    #[test]
+    fn commands() {
+        let server = server();
+        server.set_response(
+            "AdminKick \"Bob\" teamkilling",
+            "Kicked player 1. [steamid=76561198000000002] Bob",
+        );
+        let mut squad_rcon = SquadRcon::connect(server.addr(), "password").unwrap();
+
+        assert_eq!(
+            squad_rcon.kick("Bob", "teamkilling").unwrap().player_name(),
+            Some("Bob")
+        );
+        squad_rcon.ban("Bob", "1d", "cheating").unwrap();
+        let received = server.received_commands();
+        assert!(received.contains(&"AdminKick \"Bob\" teamkilling".to_string()));
+        assert!(received.contains(&"AdminBan \"Bob\" \"1d\" cheating".to_string()));
+    }
+
+    #[test]
+    fn commands_by_id() {
+        let server = server();
+        let mut squad_rcon = SquadRcon::connect(server.addr(), "password").unwrap();
+
+        let players = squad_rcon.players().unwrap();
+        squad_rcon.kick_by_id(&players[1], "teamkilling").unwrap();
+        squad_rcon.ban_by_id(3usize, "1d", "cheating").unwrap();
+        squad_rcon.force_team_change_by_id(&players[0]).unwrap();
+        let received = server.received_commands();
+        assert!(received.contains(&format!("AdminKickById {} teamkilling", players[1].id())));
+        assert!(received.contains(&"AdminBanById 3 \"1d\" cheating".to_string()));
+        assert!(received.contains(&format!("AdminForceTeamChangeById {}", players[0].id())));
+    }
 
-        let captures = MAPS_REGEX
-            .captures(&maps_string)
-            .ok_or(Error::SquadParsingError)?;
+    #[test]
+    fn squad_moderation() {
+        let server = server();
+        let mut squad_rcon = SquadRcon::connect(server.addr(), "password").unwrap();
 
-        Ok((
-            captures.get(1).expect("maps 1").as_str().to_string(),
-            captures.get(2).expect("maps 2").as_str().to_string(),
-        ))
+        let (_, squads) = squad_rcon.squads().unwrap();
+        squad_rcon.disband(&squads[1]).unwrap();
+        squad_rcon.remove_player_from_squad("Alice").unwrap();
+        let renamed = squad_rcon
+            .enforce_squad_names(|squad| squad.name() != "ARMOR")
+            .unwrap();
+        assert_eq!(renamed.len(), 1);
+        assert_eq!(renamed[0].name(), "ARMOR");
+
+        let received = server.received_commands();
+        assert!(received.contains(&"AdminDisbandSquad 2 1".to_string()));
+        assert!(received.contains(&"AdminRemovePlayerFromSquad Alice".to_string()));
+        assert!(received.contains(&"AdminRenameSquad 2 1".to_string()));
+        assert!(!received.contains(&"AdminRenameSquad 1 1".to_string()));
+    }
+
+    #[test]
+    fn server_settings() {
+        let server = server();
+        let mut squad_rcon =
+            SquadRcon::connect_with_config(server.addr(), "password", exact_config()).unwrap();
+
+        squad_rcon.set_max_num_players(80).unwrap();
+        squad_rcon.set_public_queue_limit(-1).unwrap();
+        squad_rcon.set_server_password("").unwrap();
+        squad_rcon.slomo(0.5).unwrap();
+        for result in [
+            squad_rcon.set_max_num_players(0),
+            squad_rcon.set_num_reserved_slots(101),
+            squad_rcon.set_server_password("event night"),
+            squad_rcon.set_public_queue_limit(-2),
+            squad_rcon.slomo(f32::NAN),
+        ] {
+            match result {
+                Err(Error::InvalidArgument(_)) => {}
+                other => panic!("{:?}", other),
+            }
+        }
+        assert_eq!(
+            server.received_commands(),
+            vec![
+                "AdminSetMaxNumPlayers 80",
+                "AdminSetPublicQueueLimit -1",
+                "AdminSetServerPassword",
+                "AdminSlomo 0.5"
+            ]
+        );
+    }
+
+    #[test]
+    fn layers() {
+        let server = server();
+        server.set_current_map("Current level is Narva, layer is Narva_RAAS_v1, factions USA RGF");
+        server.set_next_map("Next level is Gorodok, layer is Gorodok_AAS_v2, factions USMC MEA");
+        server.set_response(
+            "ListLayers",
+            "List of available layers :\nNarva_RAAS_v1\nGorodok_AAS_v2",
+        );
+        let mut squad_rcon =
+            SquadRcon::connect_with_config(server.addr(), "password", exact_config()).unwrap();
+
+        let (current, next) = squad_rcon.layers().unwrap();
+        assert_eq!(current.level(), Some("Narva"));
+        assert_eq!(next.unwrap().factions(), Some("USMC MEA"));
+        assert_eq!(
+            squad_rcon.maps().unwrap(),
+            ("Narva_RAAS_v1".to_string(), "Gorodok_AAS_v2".to_string())
+        );
+        // The format is learned once, and each command is only sent once
+        assert_eq!(
+            server.received_commands(),
+            vec![
+                "ShowNextMap",
+                "ShowCurrentMap",
+                "ShowCurrentMap",
+                "ShowNextMap"
+            ]
+        );
+        assert_eq!(
+            squad_rcon.list_layers().unwrap(),
+            vec!["Narva_RAAS_v1", "Gorodok_AAS_v2"]
+        );
+        let (current, _) = squad_rcon.maps_as_layers().unwrap();
+        assert_eq!(current.map(), "Narva");
+        assert_eq!(current.factions(), Some(("USA", "RGF")));
+        let layers = squad_rcon.list_maps_as_layers().unwrap();
+        assert_eq!(layers[1].game_mode(), GameMode::Raas);
+        squad_rcon.set_next_layer("Narva_RAAS_v1").unwrap();
+        assert!(server
+            .received_commands()
+            .contains(&"AdminSetNextLayer Narva_RAAS_v1".to_string()));
+    }
+
+    #[test]
+    fn layers_on_older_servers() {
+        let server = server();
+        server.ignore_command("ShowCurrentMap");
+        let config = RconConfig::new()
+            .with_read_timeout(Some(Duration::from_millis(500)))
+            .with_terminator(Terminator::Quiet(Duration::from_millis(50)));
+        let mut squad_rcon =
+            SquadRcon::connect_with_config(server.addr(), "password", config).unwrap();
+
+        let (current, next) = squad_rcon.layers().unwrap();
+        assert_eq!(current.layer(), "Narva_RAAS_v1");
+        assert_eq!(next.unwrap().layer(), "Gorodok_RAAS_v1");
+        assert_eq!(squad_rcon.snapshot().unwrap().next_map(), "Gorodok_RAAS_v1");
+        assert!(!server
+            .received_commands()
+            .contains(&"ShowCurrentMap".to_string()));
+
+        // A command which is never answered looks like a lost connection
+        match squad_rcon.raw_command("ShowCurrentMap") {
+            Err(Error::Timeout) => {}
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(squad_rcon.maps().unwrap().0, "Narva_RAAS_v1");
+    }
+
+    #[test]
+    fn chat() {
+        let server = server();
+        let mut squad_rcon = SquadRcon::connect(server.addr(), "password").unwrap();
+
+        server
+            .inject_chat("[ChatAll] [SteamID:76561198000000001] Alice : hello")
+            .unwrap();
+        squad_rcon.maps().unwrap();
+
+        let chat_log = squad_rcon.take_chat_log().unwrap();
+        assert_eq!(chat_log.len(), 1);
+        assert_eq!(chat_log[0].channel(), "ChatAll");
+        assert_eq!(chat_log[0].name(), "Alice");
+        assert_eq!(chat_log[0].message(), "hello");
+        assert!(squad_rcon.take_chat_log().unwrap().is_empty());
+    }
+
+    #[test]
+    fn fragmented_response() {
+        let server = server();
+        server.set_players(many_players(100));
+        server.set_fragment_size(Some(256));
+
+        for terminator in [
+            Terminator::EmptyResponse,
+            Terminator::ShowNextMap,
+            Terminator::Quiet(Duration::from_millis(100)),
+        ] {
+            let config = RconConfig::new().with_terminator(terminator);
+            let mut squad_rcon =
+                SquadRcon::connect_with_config(server.addr(), "password", config.clone()).unwrap();
+            assert_eq!(squad_rcon.players().unwrap().len(), 100, "{:?}", terminator);
+            let (mut squad_rcon, _) =
+                SquadRcon::connect_threaded_with_config(server.addr(), "password", config).unwrap();
+            assert_eq!(squad_rcon.players().unwrap().len(), 100, "{:?}", terminator);
+        }
+    }
+
+    #[test]
+    fn command_timeout() {
+        let server = server();
+        for threaded in [false, true] {
+            let mut squad_rcon = if threaded {
+                SquadRcon::connect_threaded(server.addr(), "password")
+                    .unwrap()
+                    .0
+            } else {
+                SquadRcon::connect(server.addr(), "password").unwrap()
+            };
+
+            server.set_response_delay(Some(Duration::from_millis(300)));
+            let timeout = Some(Duration::from_millis(100));
+            match squad_rcon.with_command_timeout(timeout, |squad_rcon| squad_rcon.players()) {
+                Err(Error::CommandTimeout) => {}
+                other => panic!("threaded: {}, {:?}", threaded, other),
+            }
+
+            // The late response to ListPlayers is discarded
+            server.set_response_delay(None);
+            assert_eq!(squad_rcon.maps().unwrap().0, "Narva_RAAS_v1");
+            assert_eq!(squad_rcon.players().unwrap().len(), 2);
+        }
     }
 }
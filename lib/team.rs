@@ -8,7 +8,7 @@ pub struct Team {
 
 impl Team {
     pub fn new(id: usize, name: String) -> Team {
-        Team { id: id, name: name }
+        Team { id, name }
     }
 
     pub fn id(&self) -> usize {
@@ -0,0 +1,53 @@
+use crate::commands;
+use crate::rcon::{RconPacket, SERVERDATA_EXECCOMMAND, SERVERDATA_RESPONSE_VALUE};
+use std::time::Duration;
+
+/// How long `Terminator::Auto` waits for the server to answer its probe
+pub(crate) const DETECTION_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How the end of a command's response is recognised.
+///
+/// Squad splits long responses over several packets, and never marks the last
+/// one. Instead, something which the server answers in order is sent after
+/// the command, and its answer ends the response.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Terminator {
+    /// Probe the server once after authenticating, and use `EmptyResponse`
+    /// if it answers the probe, or `ShowNextMap` if it does not. Connecting
+    /// and reconnecting to a server which does not answer takes up to two
+    /// seconds longer.
+    Auto,
+    /// Follow each command with an empty SERVERDATA_RESPONSE_VALUE packet,
+    /// which the server mirrors back once it has answered the command.
+    EmptyResponse,
+    /// Consider the response complete once the server has been quiet for
+    /// this long after its first packet. Nothing extra is sent, but every
+    /// command takes at least this long, and the gaps between the packets of
+    /// one response must be shorter than it.
+    ///
+    /// Commands which the server does not answer get an empty response, so
+    /// long as another command sent with them is answered. If nothing at all
+    /// arrives within the read timeout, they fail with `Error::Timeout`, as
+    /// the connection may have been lost.
+    Quiet(Duration),
+    /// Follow each command with a `ShowNextMap` command. This works with
+    /// every server, but sends twice as many commands. This is the default.
+    #[default]
+    ShowNextMap,
+}
+
+impl Terminator {
+    /// The packet which follows a command to mark the end of its response,
+    /// if there is one
+    pub(crate) fn probe(&self, id: i32) -> Option<RconPacket> {
+        match self {
+            Terminator::EmptyResponse => Some(RconPacket::new(id, SERVERDATA_RESPONSE_VALUE, "")),
+            Terminator::Auto | Terminator::ShowNextMap => Some(RconPacket::new(
+                id,
+                SERVERDATA_EXECCOMMAND,
+                commands::SHOW_NEXT_MAP,
+            )),
+            Terminator::Quiet(_) => None,
+        }
+    }
+}
@@ -0,0 +1,561 @@
+use crate::rcon::{RconClient, RconPacket, SERVERDATA_EXECCOMMAND};
+use crate::transport::{TcpTransport, Transport};
+use crate::{Error, RconConfig, ServerEvent, Terminator, SERVERDATA_CHAT};
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::net::ToSocketAddrs;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The packet id used by heartbeat probes. Ids handed out by `get_next_id`
+/// start at 10, so this never collides with a command.
+const HEARTBEAT_ID: i32 = 1;
+
+/// The write half of a connection, shared with the heartbeat thread
+type Writer<T> = Arc<Mutex<T>>;
+
+/// State shared between a `ThreadedRconClient` and the threads serving its
+/// current connection
+struct Shared {
+    /// Senders for callers waiting on a response, by packet id. This is
+    /// `None` once the reader thread has exited.
+    pending: Option<HashMap<i32, Sender<RconPacket>>>,
+    /// When a packet was last received from the server
+    last_received: Instant,
+    /// Set when the connection was closed because the server stopped
+    /// answering heartbeats
+    timed_out: bool,
+}
+
+impl Shared {
+    /// The error to report once the reader thread has exited
+    fn exit_error(&self) -> Error {
+        if self.timed_out {
+            Error::Timeout
+        } else {
+            Error::Disconnected
+        }
+    }
+}
+
+/// An rcon client where a dedicated reader thread owns the read half of the
+/// socket.
+///
+/// Responses are routed to the waiting caller by packet id. Chat messages, and
+/// other events, are delivered over a channel as soon as they arrive, instead
+/// of waiting for the next command.
+pub struct ThreadedRconClient<T: Transport = TcpTransport> {
+    next_id: i32,
+    password: String,
+    stream: Writer<T>,
+    config: RconConfig,
+    terminator: Terminator,
+    shared: Arc<Mutex<Shared>>,
+    /// Handed to each new reader thread, which keeps the event channel open
+    /// across reconnections
+    event_sender: Sender<ServerEvent>,
+}
+
+impl ThreadedRconClient<TcpTransport> {
+    /// Connect to, and authenticate with, an rcon server, and start the
+    /// reader thread.
+    ///
+    /// Chat messages and other events are sent to the returned `Receiver`.
+    pub fn connect<A: ToSocketAddrs, S: Into<String>>(
+        addr: A,
+        password: S,
+        config: RconConfig,
+    ) -> Result<(ThreadedRconClient, Receiver<ServerEvent>), Error> {
+        ThreadedRconClient::from_rcon_client(RconClient::connect_with_config(
+            addr, password, config,
+        )?)
+    }
+}
+
+impl<T: Transport> ThreadedRconClient<T> {
+    /// Start the reader thread for an authenticated client.
+    ///
+    /// Chat messages and other events are sent to the returned `Receiver`.
+    pub fn from_rcon_client(
+        rcon_client: RconClient<T>,
+    ) -> Result<(ThreadedRconClient<T>, Receiver<ServerEvent>), Error> {
+        let password = rcon_client.password().to_string();
+        let config = rcon_client.config().clone();
+        let terminator = rcon_client.terminator();
+        let (event_sender, event_receiver) = channel();
+        let (stream, next_id, early_packets) = rcon_client.into_parts();
+        send_events(early_packets, &event_sender);
+        let (stream, shared) = start(stream, &config, event_sender.clone())?;
+
+        let threaded_rcon_client = ThreadedRconClient {
+            next_id,
+            password,
+            stream,
+            config,
+            terminator,
+            shared,
+            event_sender,
+        };
+
+        Ok((threaded_rcon_client, event_receiver))
+    }
+
+    /// Reconnect and re-authenticate, starting a new reader thread.
+    ///
+    /// Events continue to be delivered to the same `Receiver`.
+    pub fn reconnect(&mut self) -> Result<(), Error> {
+        let transport = {
+            let stream = self.stream.lock().unwrap();
+            stream.shutdown().ok();
+            stream.reconnect()?
+        };
+        let rcon_client =
+            RconClient::from_transport(transport, self.password.clone(), self.config.clone())?;
+        self.terminator = rcon_client.terminator();
+        let (stream, next_id, early_packets) = rcon_client.into_parts();
+        send_events(early_packets, &self.event_sender);
+
+        let (stream, shared) = start(stream, &self.config, self.event_sender.clone())?;
+        self.stream = stream;
+        self.shared = shared;
+        self.next_id = next_id;
+        Ok(())
+    }
+
+    /// Get the next id for an rcon packet to send
+    pub fn get_next_id(&mut self) -> i32 {
+        let next_id = self.next_id;
+        self.next_id += 1;
+        if self.next_id > 100000 {
+            self.next_id = 10;
+        }
+        next_id
+    }
+
+    /// Send an rcon packet to the server
+    pub fn send_packet(&mut self, packet: &RconPacket) -> Result<(), Error> {
+        self.stream.lock().unwrap().write_all(&packet.encode()?)?;
+        Ok(())
+    }
+
+    /// Register interest in responses to the given packet ids
+    fn register(&self, ids: &[i32]) -> Result<Receiver<RconPacket>, Error> {
+        let (sender, receiver) = channel();
+        let mut shared = self.shared.lock().unwrap();
+        let exit_error = shared.exit_error();
+        let pending = shared.pending.as_mut().ok_or(exit_error)?;
+        for id in ids {
+            pending.insert(*id, sender.clone());
+        }
+        Ok(receiver)
+    }
+
+    fn unregister(&self, ids: &[i32]) {
+        if let Some(pending) = self.shared.lock().unwrap().pending.as_mut() {
+            for id in ids {
+                pending.remove(id);
+            }
+        }
+    }
+
+    /// Wait for the next packet routed to `receiver`, for at most `timeout`
+    fn recv(
+        &self,
+        receiver: &Receiver<RconPacket>,
+        timeout: Option<Duration>,
+    ) -> Result<RconPacket, Error> {
+        let result = match timeout {
+            Some(read_timeout) => receiver.recv_timeout(read_timeout),
+            None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        result.map_err(|e| match e {
+            RecvTimeoutError::Timeout => Error::Timeout,
+            RecvTimeoutError::Disconnected => self.shared.lock().unwrap().exit_error(),
+        })
+    }
+
+    /// The settings this client was connected with
+    pub fn config(&self) -> &RconConfig {
+        &self.config
+    }
+
+    /// Execute several rcon commands at once, and return each response, in
+    /// the same order as `commands`.
+    ///
+    /// Every command is sent before any response is read, and responses are
+    /// matched to commands by packet id.
+    ///
+    /// If they are not answered within `timeout`, this fails with
+    /// `Error::CommandTimeout`, and responses which arrive later are
+    /// discarded by the reader thread.
+    pub fn exec_commands<S: AsRef<str>>(
+        &mut self,
+        commands: &[S],
+        timeout: Option<Duration>,
+    ) -> Result<Vec<String>, Error> {
+        if commands.is_empty() {
+            return Ok(Vec::new());
+        }
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+        let request_ids: Vec<i32> = commands.iter().map(|_| self.get_next_id()).collect();
+        let (chk_id, check_packet) = match self.terminator {
+            Terminator::Quiet(_) => (None, None),
+            terminator => {
+                let chk_id = self.get_next_id();
+                (Some(chk_id), terminator.probe(chk_id))
+            }
+        };
+        let ids: Vec<i32> = request_ids.iter().cloned().chain(chk_id).collect();
+
+        let receiver = self.register(&ids)?;
+        let result = commands
+            .iter()
+            .zip(&request_ids)
+            .try_for_each(|(command, request_id)| {
+                self.send_packet(&RconPacket::new(
+                    *request_id,
+                    SERVERDATA_EXECCOMMAND,
+                    command.as_ref(),
+                ))
+            })
+            .and_then(|_| match &check_packet {
+                Some(check_packet) => self.send_packet(check_packet),
+                None => Ok(()),
+            })
+            .and_then(|_| self.recv_responses(&receiver, &request_ids, chk_id, deadline));
+        self.unregister(&ids);
+
+        result
+    }
+
+    /// Receive the responses to `request_ids` from `receiver`, up to the
+    /// answer to `chk_id`, or until the server goes quiet if there is none.
+    ///
+    /// Without `chk_id`, requests which were not answered by the time the
+    /// server goes quiet get an empty response, and if nothing is answered
+    /// within the read timeout, this fails with `Error::Timeout`.
+    fn recv_responses(
+        &self,
+        receiver: &Receiver<RconPacket>,
+        request_ids: &[i32],
+        chk_id: Option<i32>,
+        deadline: Option<Instant>,
+    ) -> Result<Vec<String>, Error> {
+        let mut body_parts: Vec<Vec<String>> = vec![Vec::new(); request_ids.len()];
+        loop {
+            let answered = body_parts.iter().any(|body_parts| !body_parts.is_empty());
+            let mut timeout = match self.terminator {
+                Terminator::Quiet(quiet) if answered => Some(quiet),
+                _ => self.config.read_timeout(),
+            };
+            if let Some(deadline) = deadline {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                timeout = Some(timeout.map_or(remaining, |timeout| timeout.min(remaining)));
+            }
+            let response = match self.recv(receiver, timeout) {
+                Ok(response) => response,
+                Err(Error::Timeout) if deadline.is_some_and(|d| Instant::now() >= d) => {
+                    return Err(Error::CommandTimeout)
+                }
+                Err(Error::Timeout) if chk_id.is_none() && answered => break,
+                Err(e) => return Err(e),
+            };
+            if let Some(i) = request_ids.iter().position(|id| *id == response.id()) {
+                body_parts[i].push(response.into_body());
+            } else if Some(response.id()) == chk_id {
+                break;
+            }
+        }
+        Ok(body_parts
+            .into_iter()
+            .map(|body_parts| body_parts.join(""))
+            .collect())
+    }
+}
+
+impl<T: Transport> Drop for ThreadedRconClient<T> {
+    fn drop(&mut self) {
+        // Unblock the reader thread so it exits
+        self.stream.lock().unwrap().shutdown().ok();
+    }
+}
+
+/// Send the events among `packets`, which were received before the reader
+/// thread started
+fn send_events(packets: Vec<RconPacket>, event_sender: &Sender<ServerEvent>) {
+    for packet in packets {
+        if packet.type_() == SERVERDATA_CHAT {
+            event_sender
+                .send(ServerEvent::parse(packet.body()))
+                .unwrap_or(());
+        }
+    }
+}
+
+/// Start the threads serving a newly authenticated connection, returning the
+/// write half of the socket and the state shared with those threads.
+fn start<T: Transport>(
+    stream: T,
+    config: &RconConfig,
+    event_sender: Sender<ServerEvent>,
+) -> Result<(Writer<T>, Arc<Mutex<Shared>>), Error> {
+    let shared = Arc::new(Mutex::new(Shared {
+        pending: Some(HashMap::new()),
+        last_received: Instant::now(),
+        timed_out: false,
+    }));
+
+    // The reader thread waits on the server indefinitely. Silent servers are
+    // detected by the heartbeat instead.
+    let reader_stream = stream.try_clone()?;
+    reader_stream.set_read_timeout(None)?;
+    spawn_reader(
+        reader_stream,
+        config.max_body_length(),
+        shared.clone(),
+        event_sender,
+    );
+
+    let stream = Arc::new(Mutex::new(stream));
+    if let Some(heartbeat_interval) = config.heartbeat_interval() {
+        let probe_timeout = config.read_timeout().unwrap_or(heartbeat_interval);
+        spawn_heartbeat(
+            stream.clone(),
+            shared.clone(),
+            heartbeat_interval,
+            probe_timeout,
+        );
+    }
+
+    Ok((stream, shared))
+}
+
+/// Spawn the thread which reads every packet from `stream`
+fn spawn_reader<T: Transport>(
+    mut stream: T,
+    max_body_length: usize,
+    shared: Arc<Mutex<Shared>>,
+    event_sender: Sender<ServerEvent>,
+) {
+    thread::spawn(move || {
+        loop {
+            let packet = match RconPacket::decode(&mut stream, max_body_length) {
+                Ok(packet) => packet,
+                Err(e) => {
+                    debug!("rcon reader thread exiting: {}", e);
+                    break;
+                }
+            };
+
+            if packet.type_() == SERVERDATA_CHAT {
+                shared.lock().unwrap().last_received = Instant::now();
+                // The receiver may have been dropped, in which case events are
+                // simply discarded
+                event_sender
+                    .send(ServerEvent::parse(packet.body()))
+                    .unwrap_or(());
+                continue;
+            }
+
+            let sender = {
+                let mut shared = shared.lock().unwrap();
+                shared.last_received = Instant::now();
+                shared
+                    .pending
+                    .as_ref()
+                    .and_then(|pending| pending.get(&packet.id()).cloned())
+            };
+            match sender {
+                Some(sender) => sender.send(packet).unwrap_or(()),
+                None => debug!("Discarding packet with unknown id {}", packet.id()),
+            }
+        }
+
+        // Dropping every sender wakes all waiting callers with a disconnect
+        shared.lock().unwrap().pending.take();
+    });
+}
+
+/// Spawn the thread which probes the server whenever it has been silent for
+/// `interval`, closing the connection if the probe is not answered within
+/// `probe_timeout`.
+fn spawn_heartbeat<T: Transport>(
+    stream: Writer<T>,
+    shared: Arc<Mutex<Shared>>,
+    interval: Duration,
+    probe_timeout: Duration,
+) {
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+
+        let receiver = {
+            let mut shared = shared.lock().unwrap();
+            if shared.last_received.elapsed() < interval {
+                continue;
+            }
+            let (sender, receiver) = channel();
+            match shared.pending.as_mut() {
+                Some(pending) => pending.insert(HEARTBEAT_ID, sender),
+                // The connection is already closed
+                None => break,
+            };
+            receiver
+        };
+
+        let probe = RconPacket::new(HEARTBEAT_ID, SERVERDATA_EXECCOMMAND, "ShowNextMap");
+        let sent = probe
+            .encode()
+            .and_then(|data| Ok(stream.lock().unwrap().write_all(&data)?));
+
+        let answer = match sent {
+            Ok(()) => receiver.recv_timeout(probe_timeout),
+            Err(_) => Err(RecvTimeoutError::Timeout),
+        };
+        if let Some(pending) = shared.lock().unwrap().pending.as_mut() {
+            pending.remove(&HEARTBEAT_ID);
+        }
+
+        match answer {
+            Ok(_) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+            Err(RecvTimeoutError::Timeout) => {
+                warn!("Server did not answer heartbeat, closing connection");
+                shared.lock().unwrap().timed_out = true;
+                stream.lock().unwrap().shutdown().ok();
+                break;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ThreadedRconClient;
+    use crate::mock::fixtures::{server, PLAYERS, SQUADS};
+    use crate::mock::MockServer;
+    use crate::{Error, RconConfig, ServerEvent, SquadRcon, Terminator};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn chat_without_polling() {
+        let server = MockServer::start("password").unwrap();
+        let (_squad_rcon, event_receiver) =
+            SquadRcon::connect_threaded(server.addr(), "password").unwrap();
+
+        server
+            .inject_chat("[ChatAll] [SteamID:76561198000000001] Alice : hello")
+            .unwrap();
+        server.inject_chat("Something new happened").unwrap();
+
+        let event = event_receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        let chat = event.chat().unwrap();
+        assert_eq!(chat.steam_id(), "76561198000000001");
+        assert_eq!(chat.message(), "hello");
+        match event_receiver.recv_timeout(Duration::from_secs(5)).unwrap() {
+            ServerEvent::Unknown(body) => assert_eq!(body, "Something new happened"),
+            event => panic!("{:?}", event),
+        }
+    }
+
+    #[test]
+    fn chat_during_detection() {
+        let server = server();
+        server.set_ignore_empty_response(true);
+        let config = RconConfig::new()
+            .with_read_timeout(Some(Duration::from_secs(1)))
+            .with_terminator(Terminator::Auto);
+
+        let addr = server.addr();
+        let connecting =
+            thread::spawn(move || ThreadedRconClient::connect(addr, "password", config));
+        // Arrives while the client waits for an answer to its probe
+        thread::sleep(Duration::from_millis(200));
+        server.inject_chat("Something new happened").unwrap();
+        let (_threaded_rcon_client, events) = connecting.join().unwrap().unwrap();
+
+        match events.recv_timeout(Duration::from_secs(5)).unwrap() {
+            ServerEvent::Unknown(body) => assert_eq!(body, "Something new happened"),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn commands() {
+        let server = server();
+        let (mut squad_rcon, event_receiver) =
+            SquadRcon::connect_threaded(server.addr(), "password").unwrap();
+
+        server
+            .inject_chat("[ChatAdmin] [SteamID:76561198000000001] Alice : hi")
+            .unwrap();
+        assert_eq!(squad_rcon.maps().unwrap().1, "Gorodok_RAAS_v1");
+        assert!(squad_rcon.take_events().is_empty());
+        assert_eq!(
+            event_receiver
+                .recv_timeout(Duration::from_secs(5))
+                .unwrap()
+                .chat()
+                .unwrap()
+                .channel(),
+            "ChatAdmin"
+        );
+    }
+
+    #[test]
+    fn disconnect_and_reconnect() {
+        let server = MockServer::start("password").unwrap();
+        let (mut squad_rcon, _event_receiver) =
+            SquadRcon::connect_threaded(server.addr(), "password").unwrap();
+
+        server.disconnect_all();
+        std::thread::sleep(Duration::from_millis(100));
+        match squad_rcon.raw_command("ListPlayers") {
+            Err(Error::Disconnected) | Err(Error::IoError(_)) => {}
+            other => panic!("expected a disconnect, got {:?}", other),
+        }
+
+        squad_rcon.reconnect().unwrap();
+        assert_eq!(squad_rcon.raw_command("ListPlayers").unwrap(), "");
+    }
+
+    #[test]
+    fn heartbeat() {
+        let server = MockServer::start("password").unwrap();
+        let config = RconConfig::new()
+            .with_read_timeout(Some(Duration::from_millis(100)))
+            .with_heartbeat_interval(Some(Duration::from_millis(50)));
+        let (mut squad_rcon, _event_receiver) =
+            SquadRcon::connect_threaded_with_config(server.addr(), "password", config).unwrap();
+
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(squad_rcon.raw_command("ListPlayers").is_ok());
+
+        server.set_silent(true);
+        std::thread::sleep(Duration::from_millis(500));
+        server.set_silent(false);
+        match squad_rcon.raw_command("ListPlayers") {
+            Err(Error::Timeout) => {}
+            other => panic!("expected Timeout, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn quiet_partial_results() {
+        let server = server();
+        server.ignore_command("ShowCurrentMap");
+        let config = RconConfig::new()
+            .with_read_timeout(Some(Duration::from_millis(500)))
+            .with_terminator(Terminator::Quiet(Duration::from_millis(50)));
+
+        let (mut threaded_rcon_client, _) =
+            ThreadedRconClient::connect(server.addr(), "password", config).unwrap();
+        let responses = threaded_rcon_client
+            .exec_commands(&["ListPlayers", "ShowCurrentMap", "ListSquads"], None)
+            .unwrap();
+        assert_eq!(responses, vec![PLAYERS, "", SQUADS]);
+    }
+}
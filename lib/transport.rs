@@ -0,0 +1,497 @@
+//! The byte streams rcon packets are carried over.
+
+use crate::Error;
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// A connection to an rcon server.
+///
+/// A transport also knows how to open a new connection to the same server,
+/// which is used when reconnecting.
+pub trait Transport: Read + Write + Send + Sized + 'static {
+    /// Open a new connection to the same server
+    fn reconnect(&self) -> Result<Self, Error>;
+
+    /// Another handle to this same connection, so it can be read from one
+    /// thread while being written from another
+    fn try_clone(&self) -> Result<Self, Error>;
+
+    /// Close the connection in both directions, waking any blocked reads
+    fn shutdown(&self) -> Result<(), Error>;
+
+    /// Limit how long a read may block, or `None` to block forever
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), Error>;
+
+    /// Limit how long a write may block, or `None` to block forever
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> Result<(), Error>;
+}
+
+/// A TCP connection. This is the default transport.
+///
+/// Every address the server resolved to is kept, so reconnecting tries each
+/// of them again. A transport made with `TcpTransport::connect_host` instead
+/// resolves its host again when reconnecting, picking up DNS changes.
+pub struct TcpTransport {
+    stream: TcpStream,
+    host: Option<String>,
+    addrs: Vec<SocketAddr>,
+    addr: SocketAddr,
+    connect_timeout: Option<Duration>,
+}
+
+impl TcpTransport {
+    /// Connect to `addr`, which is anything that can be resolved to socket
+    /// addresses, such as `"example.com:21114"`, `("example.com", 21114)` or
+    /// a `SocketAddr`.
+    ///
+    /// Every resolved address is tried in turn, waiting at most
+    /// `connect_timeout` for each. If none can be connected to, the error
+    /// for each address is returned in `Error::ConnectFailed`.
+    pub fn connect<A: ToSocketAddrs>(
+        addr: A,
+        connect_timeout: Option<Duration>,
+    ) -> Result<TcpTransport, Error> {
+        let addrs = addr.to_socket_addrs()?.collect();
+        TcpTransport::connect_addrs(None, addrs, connect_timeout)
+    }
+
+    /// Connect to `host`, such as `"example.com:21114"`, as with
+    /// `TcpTransport::connect`. The host is resolved again each time the
+    /// transport reconnects.
+    pub fn connect_host<S: Into<String>>(
+        host: S,
+        connect_timeout: Option<Duration>,
+    ) -> Result<TcpTransport, Error> {
+        let host = host.into();
+        let addrs = host.as_str().to_socket_addrs()?.collect();
+        TcpTransport::connect_addrs(Some(host), addrs, connect_timeout)
+    }
+
+    fn connect_addrs(
+        host: Option<String>,
+        addrs: Vec<SocketAddr>,
+        connect_timeout: Option<Duration>,
+    ) -> Result<TcpTransport, Error> {
+        let mut errors = Vec::new();
+        for addr in addrs.iter() {
+            let stream = match connect_timeout {
+                Some(connect_timeout) => TcpStream::connect_timeout(addr, connect_timeout),
+                None => TcpStream::connect(addr),
+            };
+            match stream {
+                Ok(stream) => {
+                    return Ok(TcpTransport {
+                        stream,
+                        host,
+                        addr: *addr,
+                        addrs,
+                        connect_timeout,
+                    })
+                }
+                Err(e) => errors.push((*addr, Error::from(e))),
+            }
+        }
+        if errors.is_empty() {
+            Err(Error::NoAddresses(host.unwrap_or_default()))
+        } else {
+            Err(Error::ConnectFailed(errors))
+        }
+    }
+
+    /// Wrap an already connected stream. Reconnecting connects to the same
+    /// peer address.
+    pub fn from_stream(stream: TcpStream) -> Result<TcpTransport, Error> {
+        let addr = stream.peer_addr()?;
+        Ok(TcpTransport {
+            stream,
+            host: None,
+            addrs: vec![addr],
+            addr,
+            connect_timeout: None,
+        })
+    }
+
+    /// The host this connection was made to, if it was made with
+    /// `TcpTransport::connect_host`
+    pub fn host(&self) -> Option<&str> {
+        self.host.as_deref()
+    }
+
+    /// The address of the server this is connected to
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Read for TcpTransport {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.stream.read(buf)
+    }
+}
+
+impl Write for TcpTransport {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.stream.write(buf)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl Transport for TcpTransport {
+    fn reconnect(&self) -> Result<TcpTransport, Error> {
+        let transport = match &self.host {
+            Some(host) => TcpTransport::connect_host(host.as_str(), self.connect_timeout)?,
+            None => TcpTransport::connect_addrs(None, self.addrs.clone(), self.connect_timeout)?,
+        };
+        transport.set_read_timeout(self.stream.read_timeout()?)?;
+        transport.set_write_timeout(self.stream.write_timeout()?)?;
+        Ok(transport)
+    }
+
+    fn try_clone(&self) -> Result<TcpTransport, Error> {
+        Ok(TcpTransport {
+            stream: self.stream.try_clone()?,
+            host: self.host.clone(),
+            addrs: self.addrs.clone(),
+            addr: self.addr,
+            connect_timeout: self.connect_timeout,
+        })
+    }
+
+    fn shutdown(&self) -> Result<(), Error> {
+        self.stream.shutdown(Shutdown::Both)?;
+        Ok(())
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        self.stream.set_read_timeout(timeout)?;
+        Ok(())
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        self.stream.set_write_timeout(timeout)?;
+        Ok(())
+    }
+}
+
+/// One direction of a `MemoryTransport`
+#[derive(Default)]
+struct Pipe {
+    state: Mutex<PipeState>,
+    condvar: Condvar,
+}
+
+#[derive(Default)]
+struct PipeState {
+    buf: VecDeque<u8>,
+    closed: bool,
+}
+
+impl Pipe {
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.condvar.notify_all();
+    }
+}
+
+/// One end of an in-memory connection, for tests.
+///
+/// Bytes written to one end are read from the other. Ends are created in
+/// pairs, either directly with `MemoryTransport::pair`, or by connecting to a
+/// `MemoryListener`, which also allows reconnecting.
+pub struct MemoryTransport {
+    incoming: Arc<Pipe>,
+    outgoing: Arc<Pipe>,
+    read_timeout: Arc<Mutex<Option<Duration>>>,
+    listener: Option<Sender<MemoryTransport>>,
+}
+
+impl MemoryTransport {
+    /// Create both ends of a connection.
+    ///
+    /// Reconnecting either end fails with `Error::Disconnected`.
+    pub fn pair() -> (MemoryTransport, MemoryTransport) {
+        let a = Arc::new(Pipe::default());
+        let b = Arc::new(Pipe::default());
+        (
+            MemoryTransport {
+                incoming: a.clone(),
+                outgoing: b.clone(),
+                read_timeout: Arc::new(Mutex::new(None)),
+                listener: None,
+            },
+            MemoryTransport {
+                incoming: b,
+                outgoing: a,
+                read_timeout: Arc::new(Mutex::new(None)),
+                listener: None,
+            },
+        )
+    }
+
+    /// Connect through a listener's sender, returning the client end
+    pub(crate) fn connect(listener: &Sender<MemoryTransport>) -> MemoryTransport {
+        let (mut client, server) = MemoryTransport::pair();
+        client.listener = Some(listener.clone());
+        // If the listener is gone, the client end reads as closed
+        listener.send(server).ok();
+        client
+    }
+}
+
+impl Read for MemoryTransport {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read_timeout = *self.read_timeout.lock().unwrap();
+        let deadline = read_timeout.map(|read_timeout| Instant::now() + read_timeout);
+
+        let mut state = self.incoming.state.lock().unwrap();
+        while state.buf.is_empty() && !state.closed {
+            state = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(std::io::ErrorKind::TimedOut.into());
+                    }
+                    self.incoming
+                        .condvar
+                        .wait_timeout(state, deadline - now)
+                        .unwrap()
+                        .0
+                }
+                None => self.incoming.condvar.wait(state).unwrap(),
+            };
+        }
+
+        let length = buf.len().min(state.buf.len());
+        for (byte, b) in buf.iter_mut().zip(state.buf.drain(..length)) {
+            *byte = b;
+        }
+        Ok(length)
+    }
+}
+
+impl Write for MemoryTransport {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut state = self.outgoing.state.lock().unwrap();
+        if state.closed {
+            return Err(std::io::ErrorKind::BrokenPipe.into());
+        }
+        state.buf.extend(buf);
+        self.outgoing.condvar.notify_all();
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn reconnect(&self) -> Result<MemoryTransport, Error> {
+        let listener = self.listener.as_ref().ok_or(Error::Disconnected)?;
+        let (mut client, server) = MemoryTransport::pair();
+        listener.send(server).map_err(|_| Error::Disconnected)?;
+        client.listener = Some(listener.clone());
+        client.set_read_timeout(*self.read_timeout.lock().unwrap())?;
+        Ok(client)
+    }
+
+    fn try_clone(&self) -> Result<MemoryTransport, Error> {
+        Ok(MemoryTransport {
+            incoming: self.incoming.clone(),
+            outgoing: self.outgoing.clone(),
+            read_timeout: self.read_timeout.clone(),
+            listener: self.listener.clone(),
+        })
+    }
+
+    fn shutdown(&self) -> Result<(), Error> {
+        self.incoming.close();
+        self.outgoing.close();
+        Ok(())
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        *self.read_timeout.lock().unwrap() = timeout;
+        Ok(())
+    }
+
+    /// Writes to memory never block, so this has no effect
+    fn set_write_timeout(&self, _: Option<Duration>) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Accepts in-memory connections, the same way a `TcpListener` accepts TCP
+/// connections.
+pub struct MemoryListener {
+    sender: Sender<MemoryTransport>,
+    receiver: Receiver<MemoryTransport>,
+}
+
+impl Default for MemoryListener {
+    fn default() -> MemoryListener {
+        let (sender, receiver) = channel();
+        MemoryListener { sender, receiver }
+    }
+}
+
+impl MemoryListener {
+    pub fn new() -> MemoryListener {
+        MemoryListener::default()
+    }
+
+    /// Connect to this listener, returning the client end of the connection.
+    ///
+    /// The client end can reconnect to this listener.
+    pub fn connect(&self) -> MemoryTransport {
+        MemoryTransport::connect(&self.sender)
+    }
+
+    /// Wait for the next connection, returning the server end
+    pub fn accept(&self) -> Result<MemoryTransport, Error> {
+        self.receiver.recv().map_err(|_| Error::Disconnected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MemoryListener, MemoryTransport, TcpTransport, Transport};
+    use crate::mock::fixtures::server;
+    use crate::{Error, RconConfig, ReconnectPolicy, SquadRcon};
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    #[test]
+    fn duplex() {
+        let (mut a, mut b) = MemoryTransport::pair();
+        a.write_all(b"ping").unwrap();
+        b.write_all(b"pong").unwrap();
+
+        let mut buf = [0; 4];
+        b.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ping");
+        a.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"pong");
+    }
+
+    #[test]
+    fn shutdown_wakes_reader() {
+        let (a, mut b) = MemoryTransport::pair();
+        let reader = std::thread::spawn(move || b.read(&mut [0; 4]).unwrap());
+        std::thread::sleep(Duration::from_millis(50));
+        a.shutdown().unwrap();
+        assert_eq!(reader.join().unwrap(), 0);
+    }
+
+    #[test]
+    fn read_timeout() {
+        let (_a, mut b) = MemoryTransport::pair();
+        b.set_read_timeout(Some(Duration::from_millis(10))).unwrap();
+        match b.read(&mut [0; 4]).map_err(Error::from) {
+            Err(Error::Timeout) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn listener_reconnect() {
+        let listener = MemoryListener::new();
+        let client = listener.connect();
+        listener.accept().unwrap();
+
+        let mut client = client.reconnect().unwrap();
+        let mut server = listener.accept().unwrap();
+        client.write_all(b"hello").unwrap();
+        let mut buf = [0; 5];
+        server.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"hello");
+
+        assert!(MemoryTransport::pair().0.reconnect().is_err());
+    }
+
+    #[test]
+    fn tcp_reconnect_resolves_host() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = format!("localhost:{}", listener.local_addr().unwrap().port());
+
+        let transport = TcpTransport::connect_host(host.as_str(), None).unwrap();
+        assert_eq!(transport.host(), Some(host.as_str()));
+        assert_eq!(transport.addr(), listener.local_addr().unwrap());
+        assert_eq!(transport.reconnect().unwrap().host(), Some(host.as_str()));
+    }
+
+    #[test]
+    fn tcp_reconnect_addrs() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let transport = TcpTransport::connect(("127.0.0.1", port), None).unwrap();
+        assert_eq!(transport.host(), None);
+        let transport = transport.reconnect().unwrap();
+        assert_eq!(transport.addr(), listener.local_addr().unwrap());
+    }
+
+    #[test]
+    fn tcp_connect_failed() {
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        match TcpTransport::connect(addr, Some(Duration::from_secs(1))) {
+            Err(Error::ConnectFailed(errors)) => {
+                assert_eq!(errors.len(), 1);
+                assert_eq!(errors[0].0, addr);
+            }
+            other => panic!("{:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn in_memory() {
+        let server = server();
+        let mut squad_rcon =
+            SquadRcon::from_transport(server.connect_in_memory(), "password", RconConfig::new())
+                .unwrap();
+        squad_rcon.set_reconnect_policy(Some(
+            ReconnectPolicy::new().with_initial_backoff(Duration::from_millis(10)),
+        ));
+
+        server
+            .inject_chat("[ChatAll] [SteamID:76561198000000001] Alice : hello")
+            .unwrap();
+        assert_eq!(squad_rcon.players().unwrap().len(), 2);
+        assert_eq!(squad_rcon.take_chat_log().unwrap().len(), 1);
+
+        server.disconnect_all();
+        assert_eq!(squad_rcon.squads().unwrap().1.len(), 2);
+    }
+
+    #[test]
+    fn in_memory_threaded() {
+        let server = server();
+        let (mut squad_rcon, event_receiver) = SquadRcon::from_transport_threaded(
+            server.connect_in_memory(),
+            "password",
+            RconConfig::new(),
+        )
+        .unwrap();
+
+        server
+            .inject_chat("[ChatAll] [SteamID:76561198000000001] Alice : hello")
+            .unwrap();
+        let event = event_receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(event.chat().unwrap().message(), "hello");
+
+        server.disconnect_all();
+        assert!(squad_rcon.players().is_err());
+        squad_rcon.reconnect().unwrap();
+        assert_eq!(squad_rcon.players().unwrap().len(), 2);
+    }
+}
@@ -1,4 +1,4 @@
-use clap::{App, Arg, SubCommand};
+use clap::{value_t, App, AppSettings, Arg, SubCommand};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     use simplelog::{CombinedLogger, Config, LevelFilter, TermLogger, TerminalMode};
@@ -34,10 +34,42 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         )
         .subcommand(SubCommand::with_name("monitor").about("Print incoming messages from server"))
         .subcommand(SubCommand::with_name("players").about("List the players on the server"))
+        .subcommand(
+            SubCommand::with_name("disconnected")
+                .about("List the players who left the server recently"),
+        )
         .subcommand(SubCommand::with_name("teams").about("List the teams on the server"))
         .subcommand(SubCommand::with_name("squads").about("List the squads on the server"))
-        .subcommand(SubCommand::with_name("list_maps").about("List the maps on the server"))
+        .subcommand(
+            SubCommand::with_name("list_maps")
+                .about("List the maps on the server")
+                .arg(
+                    Arg::with_name("map")
+                        .long("map")
+                        .value_name("MAP")
+                        .help("Only list layers on this map")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("mode")
+                        .long("mode")
+                        .value_name("MODE")
+                        .help("Only list layers of this game mode, such as RAAS or TC")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name("faction")
+                        .long("faction")
+                        .value_name("FACTION")
+                        .help("Only list layers where this faction plays")
+                        .takes_value(true),
+                ),
+        )
         .subcommand(SubCommand::with_name("maps").about("Show the current and next map"))
+        .subcommand(SubCommand::with_name("list_layers").about("List the layers on the server"))
+        .subcommand(SubCommand::with_name("layers").about("Show the current and next layer"))
         .subcommand(
             SubCommand::with_name("ban")
                 .about("Ban player from server")
@@ -81,6 +113,264 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                         .required(true),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("warn_by_id")
+                .about("Warn a player by id")
+                .arg(
+                    Arg::with_name("id")
+                        .value_name("ID")
+                        .help("Player id, as shown by players")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("message")
+                        .value_name("MESSAGE")
+                        .help("Warning shown to the player")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("kick_by_id")
+                .about("Kick a player off the server by id")
+                .arg(
+                    Arg::with_name("id")
+                        .value_name("ID")
+                        .help("Player id, as shown by players")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("reason")
+                        .value_name("REASON")
+                        .help("Reason for kicking the player")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("ban_by_id")
+                .about("Ban a player from the server by id")
+                .arg(
+                    Arg::with_name("id")
+                        .value_name("ID")
+                        .help("Player id, as shown by players")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("duration")
+                        .value_name("DURATION")
+                        .help("Examples: 1h (1 hour), 1m (1 month), 0 (indefinite)")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("reason")
+                        .value_name("REASON")
+                        .help("Reason for banning the player")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("force_team_change_by_id")
+                .about("Move a player to the other team by id")
+                .arg(
+                    Arg::with_name("id")
+                        .value_name("ID")
+                        .help("Player id, as shown by players")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("remove_from_squad_by_id")
+                .about("Remove a player from their squad by id")
+                .arg(
+                    Arg::with_name("id")
+                        .value_name("ID")
+                        .help("Player id, as shown by players")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("remove_from_squad")
+                .about("Remove a player from their squad")
+                .arg(
+                    Arg::with_name("name")
+                        .value_name("PLAYER")
+                        .help("Player name or steamid")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("disband_squad")
+                .about("Disband a squad")
+                .arg(
+                    Arg::with_name("team_id")
+                        .value_name("TEAM_ID")
+                        .help("Team id, as shown by squads")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("squad_id")
+                        .value_name("SQUAD_ID")
+                        .help("Squad id, as shown by squads")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("rename_squad")
+                .about("Reset a squad's name to the default")
+                .arg(
+                    Arg::with_name("team_id")
+                        .value_name("TEAM_ID")
+                        .help("Team id, as shown by squads")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("squad_id")
+                        .value_name("SQUAD_ID")
+                        .help("Squad id, as shown by squads")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("reset_squad_names")
+                .about("Reset the name of every squad matching a pattern")
+                .arg(
+                    Arg::with_name("pattern")
+                        .value_name("PATTERN")
+                        .help("Regular expression matched against squad names")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(SubCommand::with_name("restart_match").about("Restart the current match"))
+        .subcommand(
+            SubCommand::with_name("set_max_players")
+                .about("Set the number of player slots")
+                .arg(
+                    Arg::with_name("players")
+                        .value_name("PLAYERS")
+                        .help("Between 1 and 100")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("set_reserved_slots")
+                .about("Set the number of reserved player slots")
+                .arg(
+                    Arg::with_name("slots")
+                        .value_name("SLOTS")
+                        .help("Number of reserved slots")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("set_password")
+                .about("Set the server password")
+                .arg(
+                    Arg::with_name("password")
+                        .value_name("PASSWORD")
+                        .help("New password. Leave out to remove the password")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("set_queue_limit")
+                .setting(AppSettings::AllowNegativeNumbers)
+                .about("Set the number of players who can wait in the public queue")
+                .arg(
+                    Arg::with_name("limit")
+                        .value_name("LIMIT")
+                        .help("-1 (unlimited), 0 (no queue), or the number of players")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("slomo")
+                .about("Set the speed of the game clock")
+                .arg(
+                    Arg::with_name("scale")
+                        .value_name("SCALE")
+                        .help("Examples: 1 (normal speed), 0.5 (half speed)")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("set_rule")
+                .about("Switch a match rule on or off")
+                .arg(
+                    Arg::with_name("rule")
+                        .value_name("RULE")
+                        .possible_values(&[
+                            "all_roles",
+                            "all_vehicles",
+                            "all_deployables",
+                            "no_respawn_timer",
+                            "no_team_change_timer",
+                            "no_vehicle_claiming",
+                        ])
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("state")
+                        .value_name("STATE")
+                        .possible_values(&["on", "off"])
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("preset")
+                .about("Apply or revert a preset of match rules")
+                .arg(
+                    Arg::with_name("preset")
+                        .value_name("PRESET")
+                        .possible_values(&["training", "event"])
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("revert")
+                        .long("revert")
+                        .help("Switch the preset's rules back off"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("set_next_layer")
+                .about("Set the next layer to play on the server")
+                .arg(
+                    Arg::with_name("layer")
+                        .value_name("LAYER")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("change_layer")
+                .about("Immediately end the current game and change the layer")
+                .arg(
+                    Arg::with_name("layer")
+                        .value_name("LAYER")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
         .subcommand(
             SubCommand::with_name("set_next_map")
                 .about("Set the next map to play on the server")
@@ -126,9 +416,28 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let host = matches.value_of("host").unwrap();
     let password = matches.value_of("password").unwrap();
 
-    let mut squad_rcon = squad_rcon::SquadRcon::connect(host, password)?;
+    if matches.subcommand_matches("monitor").is_some() {
+        let (mut squad_rcon, event_receiver) = squad_rcon::SquadRcon::connect_host_threaded(
+            host,
+            password,
+            squad_rcon::RconConfig::default(),
+        )?;
+        loop {
+            // The channel stays open while squad_rcon lives, even once the
+            // connection is lost, so that is noticed by sending a command
+            match event_receiver.recv_timeout(std::time::Duration::from_secs(30)) {
+                Ok(event) => println!("{:?}", event),
+                Err(_) => {
+                    squad_rcon.maps()?;
+                }
+            }
+        }
+    }
+
+    let mut squad_rcon =
+        squad_rcon::SquadRcon::connect_host(host, password, squad_rcon::RconConfig::default())?;
 
-    if let Some(_) = matches.subcommand_matches("players") {
+    if matches.subcommand_matches("players").is_some() {
         for player in squad_rcon.players()? {
             println!(
                 "{} - {} - {} - {}",
@@ -144,12 +453,21 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     .unwrap_or("N/A".to_string())
             );
         }
-    } else if let Some(_) = matches.subcommand_matches("teams") {
+    } else if matches.subcommand_matches("disconnected").is_some() {
+        for player in squad_rcon.player_list()?.disconnected() {
+            println!(
+                "{} - {} - {}s ago",
+                player.name(),
+                player.steam_id(),
+                player.since_disconnect().as_secs()
+            );
+        }
+    } else if matches.subcommand_matches("teams").is_some() {
         let (teams, _) = squad_rcon.squads()?;
         for team in teams {
             println!("{}: {}", team.id(), team.name());
         }
-    } else if let Some(_) = matches.subcommand_matches("squads") {
+    } else if matches.subcommand_matches("squads").is_some() {
         let (_, squads) = squad_rcon.squads()?;
         for squad in squads {
             println!(
@@ -159,14 +477,52 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 squad.size()
             );
         }
-    } else if let Some(_) = matches.subcommand_matches("list_maps") {
-        for map in squad_rcon.list_maps()? {
-            println!("{}", map);
+    } else if let Some(matches) = matches.subcommand_matches("list_maps") {
+        let filtered = ["map", "mode", "faction"]
+            .iter()
+            .any(|arg| matches.is_present(arg));
+        if filtered {
+            let mut filter = squad_rcon::LayerFilter::new();
+            if let Some(map) = matches.value_of("map") {
+                filter = filter.with_map(map);
+            }
+            for mode in matches.values_of("mode").into_iter().flatten() {
+                let game_mode = squad_rcon::GameMode::from_name(mode)
+                    .ok_or_else(|| format!("Unknown game mode {}", mode))?;
+                filter = filter.with_game_mode(game_mode);
+            }
+            if let Some(faction) = matches.value_of("faction") {
+                filter = filter.with_faction(faction);
+            }
+            for layer in filter.filter(squad_rcon.list_maps_as_layers()?) {
+                println!("{}", layer);
+            }
+        } else {
+            for map in squad_rcon.list_maps()? {
+                println!("{}", map);
+            }
         }
-    } else if let Some(_) = matches.subcommand_matches("maps") {
+    } else if matches.subcommand_matches("maps").is_some() {
         let (current_map, next_map) = squad_rcon.maps()?;
         println!("Current map: {}", current_map);
         println!("Next map: {}", next_map);
+    } else if matches.subcommand_matches("list_layers").is_some() {
+        for layer in squad_rcon.list_layers()? {
+            println!("{}", layer);
+        }
+    } else if matches.subcommand_matches("layers").is_some() {
+        let (current_layer, next_layer) = squad_rcon.layers()?;
+        println!("Current layer: {}", current_layer);
+        match next_layer {
+            Some(next_layer) => println!("Next layer: {}", next_layer),
+            None => println!("Next layer: not set"),
+        }
+    } else if let Some(matches) = matches.subcommand_matches("set_next_layer") {
+        let layer = matches.value_of("layer").unwrap();
+        println!("{}", squad_rcon.set_next_layer(layer)?);
+    } else if let Some(matches) = matches.subcommand_matches("change_layer") {
+        let layer = matches.value_of("layer").unwrap();
+        println!("{}", squad_rcon.change_layer(layer)?);
     } else if let Some(matches) = matches.subcommand_matches("raw") {
         let command = matches.value_of("command").unwrap();
         println!("{}", squad_rcon.raw_command(command)?);
@@ -188,13 +544,76 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         let duration = matches.value_of("duration").unwrap();
         let reason = matches.value_of("reason").unwrap();
         println!("{}", squad_rcon.ban(name, duration, reason)?);
-    } else if let Some(_) = matches.subcommand_matches("monitor") {
-        loop {
-            std::thread::sleep(std::time::Duration::from_secs(5));
-            squad_rcon.maps()?;
-            for chat in squad_rcon.take_chat_log() {
-                println!("{:?}", chat);
-            }
+    } else if let Some(matches) = matches.subcommand_matches("warn_by_id") {
+        let id = value_t!(matches, "id", usize)?;
+        let message = matches.value_of("message").unwrap();
+        println!("{}", squad_rcon.warn_by_id(id, message)?);
+    } else if let Some(matches) = matches.subcommand_matches("kick_by_id") {
+        let id = value_t!(matches, "id", usize)?;
+        let reason = matches.value_of("reason").unwrap();
+        println!("{}", squad_rcon.kick_by_id(id, reason)?);
+    } else if let Some(matches) = matches.subcommand_matches("ban_by_id") {
+        let id = value_t!(matches, "id", usize)?;
+        let duration = matches.value_of("duration").unwrap();
+        let reason = matches.value_of("reason").unwrap();
+        println!("{}", squad_rcon.ban_by_id(id, duration, reason)?);
+    } else if let Some(matches) = matches.subcommand_matches("force_team_change_by_id") {
+        let id = value_t!(matches, "id", usize)?;
+        println!("{}", squad_rcon.force_team_change_by_id(id)?);
+    } else if let Some(matches) = matches.subcommand_matches("remove_from_squad_by_id") {
+        let id = value_t!(matches, "id", usize)?;
+        println!("{}", squad_rcon.remove_player_from_squad_by_id(id)?);
+    } else if let Some(matches) = matches.subcommand_matches("remove_from_squad") {
+        let name = matches.value_of("name").unwrap();
+        println!("{}", squad_rcon.remove_player_from_squad(name)?);
+    } else if let Some(matches) = matches.subcommand_matches("disband_squad") {
+        let team_id = value_t!(matches, "team_id", usize)?;
+        let squad_id = value_t!(matches, "squad_id", usize)?;
+        println!("{}", squad_rcon.disband_squad(team_id, squad_id)?);
+    } else if let Some(matches) = matches.subcommand_matches("rename_squad") {
+        let team_id = value_t!(matches, "team_id", usize)?;
+        let squad_id = value_t!(matches, "squad_id", usize)?;
+        println!("{}", squad_rcon.rename_squad(team_id, squad_id)?);
+    } else if let Some(matches) = matches.subcommand_matches("reset_squad_names") {
+        let pattern = regex::Regex::new(matches.value_of("pattern").unwrap())?;
+        for squad in squad_rcon.enforce_squad_names(|squad| !pattern.is_match(squad.name()))? {
+            println!(
+                "Reset {}: {} (team {})",
+                squad.id(),
+                squad.name(),
+                squad.team_id()
+            );
+        }
+    } else if matches.subcommand_matches("restart_match").is_some() {
+        println!("{}", squad_rcon.restart_match()?);
+    } else if let Some(matches) = matches.subcommand_matches("set_max_players") {
+        let players = value_t!(matches, "players", usize)?;
+        println!("{}", squad_rcon.set_max_num_players(players)?);
+    } else if let Some(matches) = matches.subcommand_matches("set_reserved_slots") {
+        let slots = value_t!(matches, "slots", usize)?;
+        println!("{}", squad_rcon.set_num_reserved_slots(slots)?);
+    } else if let Some(matches) = matches.subcommand_matches("set_password") {
+        let password = matches.value_of("password").unwrap_or("");
+        println!("{}", squad_rcon.set_server_password(password)?);
+    } else if let Some(matches) = matches.subcommand_matches("set_queue_limit") {
+        let limit = value_t!(matches, "limit", i32)?;
+        println!("{}", squad_rcon.set_public_queue_limit(limit)?);
+    } else if let Some(matches) = matches.subcommand_matches("slomo") {
+        let scale = value_t!(matches, "scale", f32)?;
+        println!("{}", squad_rcon.slomo(scale)?);
+    } else if let Some(matches) = matches.subcommand_matches("set_rule") {
+        let rule = squad_rcon::MatchRule::from_name(matches.value_of("rule").unwrap()).unwrap();
+        let enabled = matches.value_of("state") == Some("on");
+        println!("{}", squad_rcon.set_match_rule(rule, enabled)?);
+    } else if let Some(matches) = matches.subcommand_matches("preset") {
+        let preset = squad_rcon::Preset::from_name(matches.value_of("preset").unwrap()).unwrap();
+        let results = if matches.is_present("revert") {
+            squad_rcon.revert_preset(&preset)?
+        } else {
+            squad_rcon.apply_preset(&preset)?
+        };
+        for result in results {
+            println!("{}", result);
         }
     } else {
         println!("No command specified. Try --help");